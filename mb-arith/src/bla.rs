@@ -0,0 +1,198 @@
+/*
+    Bilinear approximation for perturbation
+    By Bill Wood
+
+    While a pixel's delta dz is small enough next to the reference orbit, the dz*dz term of
+    dz' = 2*Z*dz + dz*dz + dc can be dropped, leaving a step that is linear in both dz and dc:
+        dz' = A*dz + B*dc, with A = 2*Z and B = 1
+    Two such steps in a row make another one, so runs of steps can be merged into a single step:
+        A = Ay*Ax, B = Ay*Bx + By
+    Each step is only valid while |dz| < R.  A single step from Z is valid while |dz| < e*|2*Z|,
+    ie while the dropped dz*dz is less than e times the 2*Z*dz that's kept, and a merged step is
+    valid while the first step is, and its result is within the second step's radius for every dc
+    of the view:
+        R = min(Rx, (Ry - |Bx|*|dc|)/|Ax|)
+    The table holds the merged steps of 2, 4, 8, ... iterations starting at iterations 1, 3, 5, ...
+    for the 2 step level, 1, 5, 9, ... for the 4 step level, and so on, so a pixel can take the
+    longest valid step from wherever it is in the reference orbit.
+    The radii keep dz much smaller than Z, so a pixel can't need rebasing part way through a step.
+    Tolerance: since dz stays within a relative e of linear, the iteration counts match plain
+    perturbation, and so the HP kernel, apart from pixels whose |z| comes within a relative e of
+    the bailout radius, which can count an iteration more or less; the final |z| and distance can
+    differ by a little more than that, as the differences grow over the remaining iterations.
+    A, B and R can go far past the f64 range on deep zooms, so the table is built in FloatExp, then
+    stored in the pixels' delta type.
+*/
+
+use crate::{ FloatExp, Delta, ReferenceOrbit, pixel_distance };
+use crate::series::{ Complex, complex_mul, complex_add, complex_abs };
+
+// the largest the dropped dz*dz term can be as a fraction of the 2*Z*dz term; 2^-53 keeps it below the
+// rounding of the f64 perturbation steps, and larger values are only a little faster
+const BLA_EPSILON: f64 = 1.0/(1u64 << 53) as f64;
+
+// a step while building the table
+#[derive(Clone, Copy)]
+struct Bla {
+    a: Complex,
+    b: Complex,
+    r: FloatExp,
+}
+
+impl Bla {
+    // the step x followed by the step y, for |dc| <= radius
+    fn merge(x: &Bla, y: &Bla, radius: FloatExp) -> Bla {
+        let zero = FloatExp::zero();
+        let ry = (y.r - complex_abs(x.b)*radius)/complex_abs(x.a);
+        Bla {
+            a: complex_mul(y.a, x.a),
+            b: complex_add(complex_mul(y.a, x.b), y.b),
+            r: if ry < zero { zero } else if ry < x.r { ry } else { x.r },
+        }
+    }
+}
+
+// a step in the pixels' delta type
+#[derive(Clone, Copy)]
+struct Step<D> {
+    ax: D,
+    ay: D,
+    bx: D,
+    by: D,
+    // the square of the radius |dz| has to be within
+    r2: D,
+}
+
+pub struct BlaTable<D> {
+    // levels[k][i] is the step of 2^k iterations starting at iteration 1 + i*2^k, for k >= 1
+    levels: Vec<Vec<Step<D>>>,
+}
+
+impl<D: Delta> BlaTable<D> {
+    // radius is the largest |dc| of the pixels the table will be used for; for f64 deltas, A, B and R
+    // can over or underflow, but only in steps too short or too long to be valid for any pixel
+    pub fn new(orbit: &ReferenceOrbit, radius: FloatExp) -> BlaTable<D> {
+        let z = &orbit.z;
+        let last = z.len() - 1;
+        let (one, two, epsilon) = (FloatExp::from(1.0), FloatExp::from(2.0), FloatExp::from(BLA_EPSILON));
+
+        // single steps from iterations 1 to last - 1; Z_0 = 0, so there's no step from iteration 0
+        let mut steps: Vec<Bla> = (1..last).map(| m | {
+            let a = (two*FloatExp::from(z[m].0), two*FloatExp::from(z[m].1));
+            Bla { a, b: (one, FloatExp::zero()), r: epsilon*complex_abs(a) }
+        }).collect();
+
+        // single steps aren't used, since they're no faster than perturbation and less accurate
+        let mut levels = vec![];
+        while steps.len() >= 2 {
+            steps = steps.chunks_exact(2).map(| xy | Bla::merge(&xy[0], &xy[1], radius)).collect();
+            levels.push(steps.iter().map(| bla | Step {
+                ax: D::from_floatexp(bla.a.0),
+                ay: D::from_floatexp(bla.a.1),
+                bx: D::from_floatexp(bla.b.0),
+                by: D::from_floatexp(bla.b.1),
+                r2: D::from_floatexp(bla.r.sq()),
+            }).collect());
+        }
+        BlaTable { levels }
+    }
+
+    // the longest valid step of at most max_steps iterations from iteration m, where |dz|^2 = dz_norm;
+    // the steps all start at odd iterations
+    #[inline]
+    fn lookup(&self, m: usize, dz_norm: D, max_steps: usize) -> Option<(&Step<D>, usize)> {
+        let top = ((m - 1).trailing_zeros() as usize).min(self.levels.len());
+        for level in (1..=top).rev() {
+            let steps = 1 << level;
+            if steps > max_steps {
+                continue;
+            }
+            match self.levels[level - 1].get((m - 1) >> level) {
+                Some(step) if dz_norm < step.r2 => return Some((step, steps)),
+                _ => (),
+            }
+        }
+        None
+    }
+}
+
+// same as count_iterations_perturbed_smooth, taking the steps in table where they're valid; the counts
+// match count_iterations_hp the same way (see tests/bla.rs)
+pub fn count_iterations_bla_smooth<D: Delta>(orbit: &ReferenceOrbit, table: &BlaTable<D>, dcx: D, dcy: D, max_iterations: i32, bailout: f64) -> (i32, f64) {
+    let (count, norm, _) = bla_iterations(orbit, table, dcx, dcy, None, max_iterations, bailout);
+    (count, norm)
+}
+
+// same as count_iterations_perturbed_distance, taking the steps in table where they're valid
+pub fn count_iterations_bla_distance<D: Delta>(orbit: &ReferenceOrbit, table: &BlaTable<D>, dcx: D, dcy: D, dx: FloatExp, max_iterations: i32, bailout: f64) -> (i32, f64, f64) {
+    bla_iterations(orbit, table, dcx, dcy, Some(dx), max_iterations, bailout)
+}
+
+// the perturbation loop of perturbed_smooth_from, where each pass takes either a table step or a
+// perturbation step; with a pixel size, also tracks der = dz/dc of the pixel's orbit for the distance
+fn bla_iterations<D: Delta>(orbit: &ReferenceOrbit, table: &BlaTable<D>, dcx: D, dcy: D, pixel_size: Option<FloatExp>, max_iterations: i32, bailout: f64) -> (i32, f64, f64) {
+    let z = &orbit.z;
+    let last = z.len() - 1;
+    let two = D::from_f64(2.0);
+    let bailout = D::from_f64(bailout);
+    let mut count = 0;
+    let mut m = 0;
+    let zero = D::from_f64(0.0);
+    let (mut dzx, mut dzy) = (zero, zero);
+
+    let (one_fe, two_fe) = (FloatExp::from(1.0), FloatExp::from(2.0));
+    // dz/dc of the z of iteration count, starting from z = 0
+    let mut der = (FloatExp::zero(), FloatExp::zero());
+
+    while count < max_iterations {
+        let step = if m % 2 == 1 { table.lookup(m, dzx*dzx + dzy*dzy, (max_iterations - count) as usize) } else { None };
+        let steps = match step {
+            Some((step, steps)) => {
+                // dz = A*dz + B*dc, der = A*der + B
+                if pixel_size.is_some() {
+                    let a = (step.ax.to_floatexp(), step.ay.to_floatexp());
+                    der = complex_add(complex_mul(a, der), (step.bx.to_floatexp(), step.by.to_floatexp()));
+                }
+                let new_dzx = step.ax*dzx - step.ay*dzy + step.bx*dcx - step.by*dcy;
+                dzy = step.ax*dzy + step.ay*dzx + step.bx*dcy + step.by*dcx;
+                dzx = new_dzx;
+                steps
+            }
+            None => {
+                // der = 2*z*der + 1
+                let zx = D::from_f64(z[m].0);
+                let zy = D::from_f64(z[m].1);
+                if pixel_size.is_some() {
+                    let (fx, fy) = (FloatExp::from((zx + dzx).to_f64()), FloatExp::from((zy + dzy).to_f64()));
+                    der = (two_fe*(fx*der.0 - fy*der.1) + one_fe, two_fe*(fx*der.1 + fy*der.0));
+                }
+
+                // dz = 2*Z*dz + dz*dz + dc
+                let new_dzx = (two*zx + dzx)*dzx - (two*zy + dzy)*dzy + dcx;
+                dzy = two*((zx + dzx)*dzy + zy*dzx) + dcy;
+                dzx = new_dzx;
+                1
+            }
+        };
+        m += steps;
+        count += steps as i32 - 1;
+
+        let fx = D::from_f64(z[m].0) + dzx;
+        let fy = D::from_f64(z[m].1) + dzy;
+        let norm = fx*fx + fy*fy;
+        if norm >= bailout {
+            let norm = norm.to_f64();
+            let distance = pixel_size.map_or(0.0, | dx | pixel_distance(norm, ((der.0.sq() + der.1.sq())*dx*dx).to_f64()));
+            return (count, norm, distance);
+        }
+
+        // rebase as in perturbed_smooth_from
+        if norm < dzx*dzx + dzy*dzy || m == last {
+            dzx = fx;
+            dzy = fy;
+            m = 0;
+        }
+        count += 1;
+    }
+    (-1, 0.0, 0.0)
+}
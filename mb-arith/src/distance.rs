@@ -0,0 +1,152 @@
+/*
+    Exterior distance estimation
+    By Bill Wood
+
+    Along with z, the kernels here iterate its derivative with respect to c:
+        dz' = 2*z*dz + 1, starting from dz = 1 at z = c
+    For an escaped point the distance to the Mandelbrot set is then about |z|*ln(|z|*|z|)/|dz|,
+    to within a factor of 4.  Distances are returned in pixels, dividing by the pixel spacing dx,
+    so filaments much thinner than a pixel can still be drawn where the distance is under 1.
+    Points in the set have distance 0.
+    The f64 kernel keeps dz in f64; the high precision kernel keeps it in FloatExp, since dz grows
+    about as fast as the zoom and would overflow f64 at deep zooms.
+*/
+
+use crate::{ Limb, HPData, DoubleDouble, FloatExp, Formula, Mandelbrot, sq, add, t_to_f64, bailout_chunks, escaped_hp,
+    periodicity_save, in_main_cardioid_or_bulb, in_main_cardioid_or_bulb_dd, in_main_cardioid_or_bulb_hp };
+
+// the distance in pixels, given the final zx*zx + zy*zy and |dz|^2*dx^2
+#[inline]
+pub(crate) fn pixel_distance(norm: f64, dz_norm_dx2: f64) -> f64 {
+    norm.sqrt()*norm.ln()/dz_norm_dx2.sqrt()
+}
+
+// *** low precision *** //
+// returns the iteration count, the final value of zx*zx + zy*zy for smooth_iteration_count, and the distance in pixels
+#[inline]
+pub fn count_iterations_distance(x: f64, y: f64, dx: f64, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64, f64) {
+    if in_main_cardioid_or_bulb(x, y) {
+        return (-1, 0.0, 0.0);
+    }
+
+    let mut count = 0;
+    let mut zx = x;
+    let mut zy = y;
+    let (mut dzx, mut dzy) = (1.0, 0.0);
+    let mut norm = zx*zx + zy*zy;
+    let (mut check_zx, mut check_zy) = (zx, zy);
+
+    while count < max_iterations && norm < bailout {
+        // dz = 2*z*dz + 1
+        (dzx, dzy) = (2.0*(zx*dzx - zy*dzy) + 1.0, 2.0*(zx*dzy + zy*dzx));
+        (zx, zy) = Mandelbrot.step(zx, zy, x, y);
+        count += 1;
+        norm = zx*zx + zy*zy;
+
+        if periodicity {
+            if zx == check_zx && zy == check_zy {
+                return (-1, 0.0, 0.0);
+            }
+            if periodicity_save(count) {
+                (check_zx, check_zy) = (zx, zy);
+            }
+        }
+    }
+
+    if count < max_iterations {
+        (count, norm, pixel_distance(norm, (dzx*dzx + dzy*dzy)*dx*dx))
+    } else {
+        (-1, 0.0, 0.0)
+    }
+}
+
+
+// *** double-double precision *** //
+// same as count_iterations_distance; dz doesn't need more than f64 precision
+#[inline]
+pub fn count_iterations_dd_distance(x: DoubleDouble, y: DoubleDouble, dx: f64, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64, f64) {
+    if in_main_cardioid_or_bulb_dd(x, y) {
+        return (-1, 0.0, 0.0);
+    }
+
+    let mut count = 0;
+    let mut zx = x;
+    let mut zy = y;
+    let (mut dzx, mut dzy) = (1.0, 0.0);
+
+    // the low parts are too small to matter for the bailout test
+    let mut norm = zx.hi()*zx.hi() + zy.hi()*zy.hi();
+    let (mut check_zx, mut check_zy) = (zx, zy);
+    while count < max_iterations && norm < bailout {
+        (dzx, dzy) = (2.0*(zx.hi()*dzx - zy.hi()*dzy) + 1.0, 2.0*(zx.hi()*dzy + zy.hi()*dzx));
+        (zx, zy) = Mandelbrot.step_dd(zx, zy, x, y);
+        count += 1;
+        norm = zx.hi()*zx.hi() + zy.hi()*zy.hi();
+
+        if periodicity {
+            if zx == check_zx && zy == check_zy {
+                return (-1, 0.0, 0.0);
+            }
+            if periodicity_save(count) {
+                (check_zx, check_zy) = (zx, zy);
+            }
+        }
+    }
+
+    if count < max_iterations {
+        (count, norm, pixel_distance(norm, (dzx*dzx + dzy*dzy)*dx*dx))
+    } else {
+        (-1, 0.0, 0.0)
+    }
+}
+
+
+// *** high precision *** //
+// same loop as count_iterations_hp; dx is the pixel spacing, eg from t_to_floatexp
+pub fn count_iterations_hp_distance<T>(hp_data: &mut HPData<T>, x: &[T], y: &[T], dx: FloatExp, max_iterations: i32, bailout: f64) -> (i32, f64, f64)
+where T: Limb,
+{
+    if in_main_cardioid_or_bulb_hp(hp_data, x, y) {
+        return (-1, 0.0, 0.0);
+    }
+
+    let mut count = 0;
+    hp_data.zx.copy_from_slice(x);
+    hp_data.zy.copy_from_slice(y);
+    let (one, two) = (FloatExp::from(1.0), FloatExp::from(2.0));
+    let (mut dzx, mut dzy) = (one, FloatExp::zero());
+    hp_data.check_zx.copy_from_slice(x);
+    hp_data.check_zy.copy_from_slice(y);
+
+    let bailout = bailout_chunks(bailout);
+
+    // while count < max_iterations && zx*zx + zy*zy < bailout {
+    while count < max_iterations {
+        sq(&hp_data.zx, &mut hp_data.work3, &mut hp_data.work1);
+        sq(&hp_data.zy, &mut hp_data.work3, &mut hp_data.work2);
+        add(&hp_data.work1, &hp_data.work2, &mut hp_data.work3);
+        if escaped_hp(&hp_data.work3, bailout) {
+            let norm = t_to_f64(&hp_data.work3);
+            return (count, norm, pixel_distance(norm, ((dzx*dzx + dzy*dzy)*dx*dx).to_f64()));
+        }
+
+        // dz = 2*z*dz + 1; |z| < the bailout radius, so f64 is plenty for z
+        let zx = FloatExp::from(t_to_f64(&hp_data.zx));
+        let zy = FloatExp::from(t_to_f64(&hp_data.zy));
+        (dzx, dzy) = (two*(zx*dzx - zy*dzy) + one, two*(zx*dzy + zy*dzx));
+
+        Mandelbrot.step_hp(hp_data, x, y);
+        count += 1;
+
+        if hp_data.periodicity {
+            if hp_data.zx == hp_data.check_zx && hp_data.zy == hp_data.check_zy {
+                return (-1, 0.0, 0.0);
+            }
+            if periodicity_save(count) {
+                hp_data.check_zx.copy_from_slice(&hp_data.zx);
+                hp_data.check_zy.copy_from_slice(&hp_data.zy);
+            }
+        }
+    }
+    (-1, 0.0, 0.0)
+}
@@ -0,0 +1,164 @@
+/*
+    Double-double arithmetic
+    By Bill Wood
+
+    A DoubleDouble is an unevaluated sum hi + lo of two f64s with |lo| <= ulp(hi)/2, giving about
+    106 bits of precision.  This covers zooms down to about 1e-30 many times faster than the
+    high precision arithmetic.  Algorithms are from the QD library by Hida, Li and Bailey.
+    Products use Dekker's split rather than f64::mul_add, which is a slow library call on
+    targets without FMA instructions, including wasm.
+*/
+
+use std::ops::{ Add, Sub, Mul, Neg };
+use crate::{ Mandelbrot, count_iterations_dd_formula_smooth };
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DoubleDouble {
+    hi: f64,
+    lo: f64,
+}
+
+// s + err == a + b exactly
+#[inline]
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+// same as two_sum, if |a| >= |b|
+#[inline]
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+// split a into two 26 bit halves
+#[inline]
+fn split(a: f64) -> (f64, f64) {
+    let t = 134217729.0*a;  // 2^27 + 1
+    let hi = t - (t - a);
+    (hi, a - hi)
+}
+
+// p + err == a*b exactly
+#[inline]
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a*b;
+    let (a_hi, a_lo) = split(a);
+    let (b_hi, b_lo) = split(b);
+    (p, ((a_hi*b_hi - p) + a_hi*b_lo + a_lo*b_hi) + a_lo*b_lo)
+}
+
+#[inline]
+fn two_sq(a: f64) -> (f64, f64) {
+    let p = a*a;
+    let (hi, lo) = split(a);
+    (p, ((hi*hi - p) + 2.0*hi*lo) + lo*lo)
+}
+
+impl DoubleDouble {
+    pub fn new(hi: f64, lo: f64) -> DoubleDouble {
+        let (hi, lo) = two_sum(hi, lo);
+        DoubleDouble { hi, lo }
+    }
+
+    pub fn hi(self) -> f64 {
+        self.hi
+    }
+
+    pub fn lo(self) -> f64 {
+        self.lo
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+
+    #[inline]
+    pub fn sq(self) -> DoubleDouble {
+        let (p1, p2) = two_sq(self.hi);
+        let p2 = p2 + 2.0*self.hi*self.lo + self.lo*self.lo;
+        let (hi, lo) = quick_two_sum(p1, p2);
+        DoubleDouble { hi, lo }
+    }
+
+    #[inline]
+    pub fn abs(self) -> DoubleDouble {
+        if self.hi < 0.0 { -self } else { self }
+    }
+
+    // exact multiplication by 2
+    #[inline]
+    pub fn mul2(self) -> DoubleDouble {
+        DoubleDouble { hi: 2.0*self.hi, lo: 2.0*self.lo }
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(x: f64) -> DoubleDouble {
+        DoubleDouble { hi: x, lo: 0.0 }
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+    #[inline]
+    fn add(self, rhs: DoubleDouble) -> DoubleDouble {
+        let (s1, s2) = two_sum(self.hi, rhs.hi);
+        let (t1, t2) = two_sum(self.lo, rhs.lo);
+        let (s1, s2) = quick_two_sum(s1, s2 + t1);
+        let (hi, lo) = quick_two_sum(s1, s2 + t2);
+        DoubleDouble { hi, lo }
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = DoubleDouble;
+    #[inline]
+    fn neg(self) -> DoubleDouble {
+        DoubleDouble { hi: -self.hi, lo: -self.lo }
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = DoubleDouble;
+    #[inline]
+    fn sub(self, rhs: DoubleDouble) -> DoubleDouble {
+        self + -rhs
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = DoubleDouble;
+    #[inline]
+    fn mul(self, rhs: DoubleDouble) -> DoubleDouble {
+        let (p1, p2) = two_prod(self.hi, rhs.hi);
+        let p2 = p2 + (self.hi*rhs.lo + self.lo*rhs.hi);
+        let (hi, lo) = quick_two_sum(p1, p2);
+        DoubleDouble { hi, lo }
+    }
+}
+
+pub fn count_iterations_dd(x: DoubleDouble, y: DoubleDouble, max_iterations: i32, bailout: f64, periodicity: bool) -> i32 {
+    count_iterations_dd_smooth(x, y, max_iterations, bailout, periodicity).0
+}
+
+// also returns the final value of zx*zx + zy*zy, for smooth_iteration_count
+#[inline]
+pub fn count_iterations_dd_smooth(x: DoubleDouble, y: DoubleDouble, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64) {
+    if in_main_cardioid_or_bulb_dd(x, y) {
+        return (-1, 0.0);
+    }
+    count_iterations_dd_formula_smooth(&Mandelbrot, x, y, max_iterations, bailout, periodicity)
+}
+
+// same tests as in_main_cardioid_or_bulb, in double-double so points just outside the boundary aren't skipped
+#[inline]
+pub fn in_main_cardioid_or_bulb_dd(x: DoubleDouble, y: DoubleDouble) -> bool {
+    let y2 = y.sq();
+    let xq = x - DoubleDouble::from(0.25);
+    let q = xq.sq() + y2;
+    let x1 = x + DoubleDouble::from(1.0);
+    (q*(q + xq) - DoubleDouble::from(0.25)*y2).hi() <= 0.0 || (x1.sq() + y2 - DoubleDouble::from(0.0625)).hi() < 0.0
+}
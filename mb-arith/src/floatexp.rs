@@ -0,0 +1,234 @@
+/*
+    Extended exponent floating point
+    By Bill Wood
+
+    A FloatExp is an f64 mantissa in [0.5, 1) with a separate i64 binary exponent, so it keeps
+    53 bits of precision far below the f64 range (about 1e-308).  Used for perturbation deltas
+    on zooms deeper than f64 can represent.
+*/
+
+use std::ops::{ Add, Sub, Mul, Div, Neg };
+use std::cmp::Ordering;
+use num::traits::AsPrimitive;
+use core::cmp::PartialEq;
+use crate::perturbation::Delta;
+use crate::{ Limb, negate };
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FloatExp {
+    m: f64,
+    e: i64,
+}
+
+// 2^e for e in -1022..=1023
+fn pow2(e: i64) -> f64 {
+    f64::from_bits(((e + 1023) as u64) << 52)
+}
+
+impl FloatExp {
+    pub fn new(m: f64, e: i64) -> FloatExp {
+        if m == 0.0 || !m.is_finite() {
+            return FloatExp { m, e: 0 };
+        }
+        let bits = m.to_bits();
+        let m_exp = ((bits >> 52) & 0x7FF) as i64;
+        if m_exp == 0 {
+            // subnormal
+            return FloatExp::new(m*pow2(64), e - 64);
+        }
+        FloatExp {
+            m: f64::from_bits((bits & !(0x7FF << 52)) | (1022 << 52)),
+            e: e + m_exp - 1022,
+        }
+    }
+
+    pub fn zero() -> FloatExp {
+        FloatExp { m: 0.0, e: 0 }
+    }
+
+    pub fn mantissa(self) -> f64 {
+        self.m
+    }
+
+    pub fn exponent(self) -> i64 {
+        self.e
+    }
+
+    pub fn sq(self) -> FloatExp {
+        self*self
+    }
+
+    // for self >= 0
+    pub fn sqrt(self) -> FloatExp {
+        FloatExp::new((self.m*pow2(self.e.rem_euclid(2))).sqrt(), self.e.div_euclid(2))
+    }
+
+    pub fn abs(self) -> FloatExp {
+        FloatExp { m: self.m.abs(), e: self.e }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        if self.e > 2046 {
+            self.m*f64::INFINITY
+        } else if self.e < -2044 {
+            self.m*0.0
+        } else {
+            let e1 = self.e/2;
+            self.m*pow2(e1)*pow2(self.e - e1)
+        }
+    }
+}
+
+impl From<f64> for FloatExp {
+    fn from(x: f64) -> FloatExp {
+        FloatExp::new(x, 0)
+    }
+}
+
+impl Add for FloatExp {
+    type Output = FloatExp;
+    fn add(self, rhs: FloatExp) -> FloatExp {
+        if self.m == 0.0 {
+            return rhs;
+        }
+        if rhs.m == 0.0 {
+            return self;
+        }
+        let d = self.e - rhs.e;
+        if d > 64 {
+            self
+        } else if d < -64 {
+            rhs
+        } else if d >= 0 {
+            FloatExp::new(self.m + rhs.m*pow2(-d), self.e)
+        } else {
+            FloatExp::new(self.m*pow2(d) + rhs.m, rhs.e)
+        }
+    }
+}
+
+impl Neg for FloatExp {
+    type Output = FloatExp;
+    fn neg(self) -> FloatExp {
+        FloatExp { m: -self.m, e: self.e }
+    }
+}
+
+impl Sub for FloatExp {
+    type Output = FloatExp;
+    fn sub(self, rhs: FloatExp) -> FloatExp {
+        self + -rhs
+    }
+}
+
+impl Mul for FloatExp {
+    type Output = FloatExp;
+    fn mul(self, rhs: FloatExp) -> FloatExp {
+        FloatExp::new(self.m*rhs.m, self.e + rhs.e)
+    }
+}
+
+impl Div for FloatExp {
+    type Output = FloatExp;
+    fn div(self, rhs: FloatExp) -> FloatExp {
+        FloatExp::new(self.m/rhs.m, self.e - rhs.e)
+    }
+}
+
+impl PartialOrd for FloatExp {
+    fn partial_cmp(&self, other: &FloatExp) -> Option<Ordering> {
+        // mantissas are normalized, so unless the signs match and both are non-zero, the mantissas decide
+        if self.m == 0.0 || other.m == 0.0 || (self.m < 0.0) != (other.m < 0.0) || self.e == other.e {
+            self.m.partial_cmp(&other.m)
+        } else if (self.e > other.e) == (self.m > 0.0) {
+            Some(Ordering::Greater)
+        } else {
+            Some(Ordering::Less)
+        }
+    }
+}
+
+impl Delta for FloatExp {
+    fn from_f64(x: f64) -> FloatExp {
+        FloatExp::from(x)
+    }
+
+    fn to_f64(self) -> f64 {
+        FloatExp::to_f64(self)
+    }
+
+    fn from_floatexp(x: FloatExp) -> FloatExp {
+        x
+    }
+
+    fn to_floatexp(self) -> FloatExp {
+        self
+    }
+}
+
+// convert a high precision number to a FloatExp; unlike t_to_f64 this doesn't underflow at deep zooms
+pub fn t_to_floatexp<T>(x: &[T]) -> FloatExp
+where T: Limb,
+{
+    let (half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let t_neg_test = (t_low_bits + T::one()) >> 1;
+    let neg = x[0] & t_neg_test != T::zero();
+
+    // magnitude of chunk i, taking the two's complement on the fly as in t_to_f64
+    let last = if neg { x.iter().rposition(| &xi | xi != T::zero()).unwrap_or(0) } else { x.len() - 1 };
+    let chunk = | i: usize | -> f64 {
+        if !neg {
+            AsPrimitive::<f64>::as_(x[i])
+        } else if i == last {
+            AsPrimitive::<f64>::as_(t_low_bits - x[i]) + 1.0
+        } else {
+            AsPrimitive::<f64>::as_(t_low_bits - x[i])
+        }
+    };
+
+    let Some(first) = (0..=last).find(| &i | chunk(i) != 0.0) else {
+        return FloatExp::zero();
+    };
+    // enough chunks to fill the f64 mantissa
+    let end = core::cmp::min(last, first + 64/half_bits + 1);
+    let scale = pow2(half_bits as i64);
+    let mut m = 0.0;
+    for i in first..=end {
+        m = m*scale + chunk(i);
+    }
+    let r = FloatExp::new(m, -((end*half_bits) as i64));
+    if neg { -r } else { r }
+}
+
+// convert a FloatExp to a high precision number, truncating the magnitude like HpFixed::from_decimal;
+// the integer part has to fit in chunk 0
+pub fn floatexp_to_t<T>(x: FloatExp, chunks: usize) -> Vec<T>
+where T: Limb,
+{
+    let mut out = vec![T::zero(); chunks];
+    if x.m == 0.0 {
+        return out;
+    }
+    // |x| = mantissa*2^(e - 53), and chunk i holds the bits of |x|*2^(i*half_bits) below 2^half_bits
+    let half_bits = T::HALF_BITS as i64;
+    let low_bits = u64::MAX >> (64 - half_bits);
+    let mantissa = (x.m.abs()*pow2(53)) as u128;
+    for (i, c) in out.iter_mut().enumerate() {
+        let shift = x.e - 53 + i as i64*half_bits;
+        let bits = if shift >= half_bits {
+            0
+        } else if shift >= 0 {
+            mantissa << shift
+        } else if shift > -128 {
+            mantissa >> -shift
+        } else {
+            0
+        };
+        *c = T::from_u64(bits as u64 & low_bits);
+    }
+    if x.m < 0.0 {
+        let magnitude = out.clone();
+        negate(&magnitude, &mut out);
+    }
+    out
+}
@@ -0,0 +1,374 @@
+/*
+    Fractal formulas
+    By Bill Wood
+
+    A Formula is the step z = f(z) + c of an escape time fractal, in f64, double-double and high
+    precision.  The iteration drivers here do the rest (bailout, periodicity checking, counting)
+    for any Formula, so count_iterations, count_iterations_dd and count_iterations_hp are just the
+    drivers with the Mandelbrot formula.  All the formulas here are quadratic, so the smooth
+    iteration counts use power 2.
+        Mandelbrot      z = z*z + c
+        Burning Ship    z = (|zx| + i*|zy|)^2 + c
+        Tricorn         z = conj(z)^2 + c, also called the Mandelbar set
+        Celtic          z = |zx*zx - zy*zy| + i*2*zx*zy + c
+*/
+
+use std::fmt;
+use std::str::FromStr;
+use core::cmp::PartialEq;
+use crate::{ Limb, HPData, DoubleDouble, sq, add, negate, abs, multiply, t_to_f64, bailout_chunks, escaped_hp,
+    periodicity_save, count_iterations_smooth, count_iterations_dd_smooth, count_iterations_hp_smooth };
+
+pub trait Formula {
+    // returns f(z) + c, for z = zx + i*zy and c = x + i*y
+    fn step(&self, zx: f64, zy: f64, x: f64, y: f64) -> (f64, f64);
+
+    fn step_dd(&self, zx: DoubleDouble, zy: DoubleDouble, x: DoubleDouble, y: DoubleDouble) -> (DoubleDouble, DoubleDouble);
+
+    // sets hp_data.zx and hp_data.zy to f(z) + c; on entry work1 and work2 hold zx*zx and zy*zy,
+    // and work3 to work6 are free
+    fn step_hp<T>(&self, hp_data: &mut HPData<T>, x: &[T], y: &[T])
+    where T: Limb;
+}
+
+pub struct Mandelbrot;
+
+impl Formula for Mandelbrot {
+    #[inline]
+    fn step(&self, zx: f64, zy: f64, x: f64, y: f64) -> (f64, f64) {
+        (zx*zx - zy*zy + x, 2.0*zx*zy + y)
+    }
+
+    #[inline]
+    fn step_dd(&self, zx: DoubleDouble, zy: DoubleDouble, x: DoubleDouble, y: DoubleDouble) -> (DoubleDouble, DoubleDouble) {
+        (zx.sq() - zy.sq() + x, (zx*zy).mul2() + y)
+    }
+
+    #[inline]
+    fn step_hp<T>(&self, hp_data: &mut HPData<T>, x: &[T], y: &[T])
+    where T: Limb,
+    {
+        add(&hp_data.zx, &hp_data.zx, &mut hp_data.work4);
+
+        // zx = zx*zx - zy*zy + x;
+        negate(&hp_data.work2, &mut hp_data.work3);
+        add(&hp_data.work1, &hp_data.work3, &mut hp_data.work2);
+        add(&hp_data.work2, x, &mut hp_data.zx);
+
+        // zy = 2.0*zx*zy + y;
+        multiply(&hp_data.work4, &hp_data.zy, &mut hp_data.work1, &mut hp_data.work3, &mut hp_data.work2);
+        add(&hp_data.work2, y, &mut hp_data.zy);
+    }
+}
+
+pub struct BurningShip;
+
+impl Formula for BurningShip {
+    #[inline]
+    fn step(&self, zx: f64, zy: f64, x: f64, y: f64) -> (f64, f64) {
+        (zx*zx - zy*zy + x, (2.0*zx*zy).abs() + y)
+    }
+
+    #[inline]
+    fn step_dd(&self, zx: DoubleDouble, zy: DoubleDouble, x: DoubleDouble, y: DoubleDouble) -> (DoubleDouble, DoubleDouble) {
+        (zx.sq() - zy.sq() + x, (zx*zy).mul2().abs() + y)
+    }
+
+    #[inline]
+    fn step_hp<T>(&self, hp_data: &mut HPData<T>, x: &[T], y: &[T])
+    where T: Limb,
+    {
+        add(&hp_data.zx, &hp_data.zx, &mut hp_data.work4);
+
+        // zx = zx*zx - zy*zy + x;
+        negate(&hp_data.work2, &mut hp_data.work3);
+        add(&hp_data.work1, &hp_data.work3, &mut hp_data.work2);
+        add(&hp_data.work2, x, &mut hp_data.zx);
+
+        // zy = |2.0*zx*zy| + y;
+        multiply(&hp_data.work4, &hp_data.zy, &mut hp_data.work1, &mut hp_data.work3, &mut hp_data.work2);
+        abs(&hp_data.work2, &mut hp_data.work3);
+        add(&hp_data.work3, y, &mut hp_data.zy);
+    }
+}
+
+pub struct Tricorn;
+
+impl Formula for Tricorn {
+    #[inline]
+    fn step(&self, zx: f64, zy: f64, x: f64, y: f64) -> (f64, f64) {
+        (zx*zx - zy*zy + x, -2.0*zx*zy + y)
+    }
+
+    #[inline]
+    fn step_dd(&self, zx: DoubleDouble, zy: DoubleDouble, x: DoubleDouble, y: DoubleDouble) -> (DoubleDouble, DoubleDouble) {
+        (zx.sq() - zy.sq() + x, y - (zx*zy).mul2())
+    }
+
+    #[inline]
+    fn step_hp<T>(&self, hp_data: &mut HPData<T>, x: &[T], y: &[T])
+    where T: Limb,
+    {
+        add(&hp_data.zx, &hp_data.zx, &mut hp_data.work4);
+
+        // zx = zx*zx - zy*zy + x;
+        negate(&hp_data.work2, &mut hp_data.work3);
+        add(&hp_data.work1, &hp_data.work3, &mut hp_data.work2);
+        add(&hp_data.work2, x, &mut hp_data.zx);
+
+        // zy = -2.0*zx*zy + y;
+        multiply(&hp_data.work4, &hp_data.zy, &mut hp_data.work1, &mut hp_data.work3, &mut hp_data.work2);
+        negate(&hp_data.work2, &mut hp_data.work3);
+        add(&hp_data.work3, y, &mut hp_data.zy);
+    }
+}
+
+pub struct Celtic;
+
+impl Formula for Celtic {
+    #[inline]
+    fn step(&self, zx: f64, zy: f64, x: f64, y: f64) -> (f64, f64) {
+        ((zx*zx - zy*zy).abs() + x, 2.0*zx*zy + y)
+    }
+
+    #[inline]
+    fn step_dd(&self, zx: DoubleDouble, zy: DoubleDouble, x: DoubleDouble, y: DoubleDouble) -> (DoubleDouble, DoubleDouble) {
+        ((zx.sq() - zy.sq()).abs() + x, (zx*zy).mul2() + y)
+    }
+
+    #[inline]
+    fn step_hp<T>(&self, hp_data: &mut HPData<T>, x: &[T], y: &[T])
+    where T: Limb,
+    {
+        add(&hp_data.zx, &hp_data.zx, &mut hp_data.work4);
+
+        // zx = |zx*zx - zy*zy| + x;
+        negate(&hp_data.work2, &mut hp_data.work3);
+        add(&hp_data.work1, &hp_data.work3, &mut hp_data.work2);
+        abs(&hp_data.work2, &mut hp_data.work3);
+        add(&hp_data.work3, x, &mut hp_data.zx);
+
+        // zy = 2.0*zx*zy + y;
+        multiply(&hp_data.work4, &hp_data.zy, &mut hp_data.work1, &mut hp_data.work3, &mut hp_data.work2);
+        add(&hp_data.work2, y, &mut hp_data.zy);
+    }
+}
+
+
+// *** iteration drivers *** //
+pub fn count_iterations_formula<F: Formula>(formula: &F, x: f64, y: f64, max_iterations: i32, bailout: f64, periodicity: bool) -> i32 {
+    count_iterations_formula_smooth(formula, x, y, max_iterations, bailout, periodicity).0
+}
+
+// also returns the final value of zx*zx + zy*zy, for smooth_iteration_count
+#[inline]
+pub fn count_iterations_formula_smooth<F: Formula>(formula: &F, x: f64, y: f64, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64) {
+    iterate_formula_smooth(formula, x, y, x, y, max_iterations, bailout, periodicity)
+}
+
+// count_iterations_formula_smooth starting from z = zx + i*zy instead of c, eg at the pixel of a Julia set
+#[inline]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn iterate_formula_smooth<F: Formula>(formula: &F, mut zx: f64, mut zy: f64, x: f64, y: f64, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64) {
+    let mut count = 0;
+    let mut norm = zx*zx + zy*zy;
+    let (mut check_zx, mut check_zy) = (zx, zy);
+
+    while count < max_iterations && norm < bailout {
+        (zx, zy) = formula.step(zx, zy, x, y);
+        count += 1;
+        norm = zx*zx + zy*zy;
+
+        if periodicity {
+            if zx == check_zx && zy == check_zy {
+                return (-1, 0.0);
+            }
+            if periodicity_save(count) {
+                (check_zx, check_zy) = (zx, zy);
+            }
+        }
+    }
+
+    if count < max_iterations {
+        (count, norm)
+    } else {
+        (-1, 0.0)
+    }
+}
+
+pub fn count_iterations_dd_formula<F: Formula>(formula: &F, x: DoubleDouble, y: DoubleDouble, max_iterations: i32, bailout: f64, periodicity: bool) -> i32 {
+    count_iterations_dd_formula_smooth(formula, x, y, max_iterations, bailout, periodicity).0
+}
+
+// also returns the final value of zx*zx + zy*zy, for smooth_iteration_count
+#[inline]
+pub fn count_iterations_dd_formula_smooth<F: Formula>(formula: &F, x: DoubleDouble, y: DoubleDouble, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64) {
+    iterate_dd_formula_smooth(formula, x, y, x, y, max_iterations, bailout, periodicity)
+}
+
+// count_iterations_dd_formula_smooth starting from z = zx + i*zy instead of c, eg at the pixel of a Julia set
+#[inline]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn iterate_dd_formula_smooth<F: Formula>(formula: &F, mut zx: DoubleDouble, mut zy: DoubleDouble, x: DoubleDouble, y: DoubleDouble, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64) {
+    let mut count = 0;
+
+    // the low parts are too small to matter for the bailout test
+    let mut norm = zx.hi()*zx.hi() + zy.hi()*zy.hi();
+    let (mut check_zx, mut check_zy) = (zx, zy);
+    while count < max_iterations && norm < bailout {
+        (zx, zy) = formula.step_dd(zx, zy, x, y);
+        count += 1;
+        norm = zx.hi()*zx.hi() + zy.hi()*zy.hi();
+
+        if periodicity {
+            if zx == check_zx && zy == check_zy {
+                return (-1, 0.0);
+            }
+            if periodicity_save(count) {
+                (check_zx, check_zy) = (zx, zy);
+            }
+        }
+    }
+
+    if count < max_iterations {
+        (count, norm)
+    } else {
+        (-1, 0.0)
+    }
+}
+
+pub fn count_iterations_hp_formula<F, T>(formula: &F, hp_data: &mut HPData<T>, x: &[T], y: &[T], max_iterations: i32, bailout: f64) -> i32
+where F: Formula,
+    T: Limb,
+{
+    iterate_hp_formula(formula, hp_data, x, y, x, y, max_iterations, bailout)
+}
+
+// count_iterations_hp_formula starting from z = zx + i*zy instead of c, eg at the pixel of a Julia set
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn iterate_hp_formula<F, T>(formula: &F, hp_data: &mut HPData<T>, zx: &[T], zy: &[T], x: &[T], y: &[T], max_iterations: i32, bailout: f64) -> i32
+where F: Formula,
+    T: Limb,
+{
+    hp_data.zx.copy_from_slice(zx);
+    hp_data.zy.copy_from_slice(zy);
+    hp_data.check_zx.copy_from_slice(zx);
+    hp_data.check_zy.copy_from_slice(zy);
+    continue_iterations_hp_formula(formula, hp_data, x, y, 0, max_iterations, bailout)
+}
+
+// count_iterations_hp_formula from iteration count on, with z and the saved z already in hp_data
+pub(crate) fn continue_iterations_hp_formula<F, T>(formula: &F, hp_data: &mut HPData<T>, x: &[T], y: &[T], mut count: i32, max_iterations: i32, bailout: f64) -> i32
+where F: Formula,
+    T: Limb,
+{
+    let bailout = bailout_chunks(bailout);
+
+    // while count < max_iterations && zx*zx + zy*zy < bailout {
+    while count < max_iterations {
+        sq(&hp_data.zx, &mut hp_data.work3, &mut hp_data.work1);
+        sq(&hp_data.zy, &mut hp_data.work3, &mut hp_data.work2);
+        add(&hp_data.work1, &hp_data.work2, &mut hp_data.work3);
+        if escaped_hp(&hp_data.work3, bailout) {
+            return count;
+        }
+
+        formula.step_hp(hp_data, x, y);
+        count += 1;
+
+        if hp_data.periodicity {
+            if hp_data.zx == hp_data.check_zx && hp_data.zy == hp_data.check_zy {
+                return -1;
+            }
+            if periodicity_save(count) {
+                hp_data.check_zx.copy_from_slice(&hp_data.zx);
+                hp_data.check_zy.copy_from_slice(&hp_data.zy);
+            }
+        }
+    }
+    -1
+}
+
+// also returns the final value of zx*zx + zy*zy, for smooth_iteration_count
+pub fn count_iterations_hp_formula_smooth<F, T>(formula: &F, hp_data: &mut HPData<T>, x: &[T], y: &[T], max_iterations: i32, bailout: f64) -> (i32, f64)
+where F: Formula,
+    T: Limb,
+{
+    // count_iterations_hp_formula leaves zx*zx + zy*zy in work3 when a point escapes
+    let count = count_iterations_hp_formula(formula, hp_data, x, y, max_iterations, bailout);
+    if count < 0 {
+        (count, 0.0)
+    } else {
+        (count, t_to_f64(&hp_data.work3))
+    }
+}
+
+
+// *** formulas by name *** //
+// for picking a formula at run time, eg from a compute request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormulaKind {
+    Mandelbrot,
+    BurningShip,
+    Tricorn,
+    Celtic,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseFormulaError(String);
+
+impl fmt::Display for ParseFormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown formula \"{}\"; expected mandelbrot, burningShip, tricorn or celtic", self.0)
+    }
+}
+
+impl std::error::Error for ParseFormulaError {}
+
+impl FromStr for FormulaKind {
+    type Err = ParseFormulaError;
+
+    fn from_str(s: &str) -> Result<FormulaKind, ParseFormulaError> {
+        match s {
+            "mandelbrot" => Ok(FormulaKind::Mandelbrot),
+            "burningShip" => Ok(FormulaKind::BurningShip),
+            "tricorn" | "mandelbar" => Ok(FormulaKind::Tricorn),
+            "celtic" => Ok(FormulaKind::Celtic),
+            _ => Err(ParseFormulaError(s.to_string())),
+        }
+    }
+}
+
+// Mandelbrot goes to the regular functions, which also skip the main cardioid and period 2 bulb
+impl FormulaKind {
+    #[inline]
+    pub fn count_iterations_smooth(self, x: f64, y: f64, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64) {
+        match self {
+            FormulaKind::Mandelbrot => count_iterations_smooth(x, y, max_iterations, bailout, periodicity),
+            FormulaKind::BurningShip => count_iterations_formula_smooth(&BurningShip, x, y, max_iterations, bailout, periodicity),
+            FormulaKind::Tricorn => count_iterations_formula_smooth(&Tricorn, x, y, max_iterations, bailout, periodicity),
+            FormulaKind::Celtic => count_iterations_formula_smooth(&Celtic, x, y, max_iterations, bailout, periodicity),
+        }
+    }
+
+    #[inline]
+    pub fn count_iterations_dd_smooth(self, x: DoubleDouble, y: DoubleDouble, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64) {
+        match self {
+            FormulaKind::Mandelbrot => count_iterations_dd_smooth(x, y, max_iterations, bailout, periodicity),
+            FormulaKind::BurningShip => count_iterations_dd_formula_smooth(&BurningShip, x, y, max_iterations, bailout, periodicity),
+            FormulaKind::Tricorn => count_iterations_dd_formula_smooth(&Tricorn, x, y, max_iterations, bailout, periodicity),
+            FormulaKind::Celtic => count_iterations_dd_formula_smooth(&Celtic, x, y, max_iterations, bailout, periodicity),
+        }
+    }
+
+    pub fn count_iterations_hp_smooth<T>(self, hp_data: &mut HPData<T>, x: &[T], y: &[T], max_iterations: i32, bailout: f64) -> (i32, f64)
+    where T: Limb,
+    {
+        match self {
+            FormulaKind::Mandelbrot => count_iterations_hp_smooth(hp_data, x, y, max_iterations, bailout),
+            FormulaKind::BurningShip => count_iterations_hp_formula_smooth(&BurningShip, hp_data, x, y, max_iterations, bailout),
+            FormulaKind::Tricorn => count_iterations_hp_formula_smooth(&Tricorn, hp_data, x, y, max_iterations, bailout),
+            FormulaKind::Celtic => count_iterations_hp_formula_smooth(&Celtic, hp_data, x, y, max_iterations, bailout),
+        }
+    }
+}
@@ -0,0 +1,308 @@
+/*
+    Full width high precision numbers
+    By Bill Wood
+
+    The Limb functions only use the lower half of each limb, so that the product of two limbs and
+    the carries of add fit in the limb.  These use all 64 bits of u64 limbs instead: products are
+    widened to u128 and carries are handled explicitly, so each limb carries twice as many bits as a
+    half width u64 limb, and the arithmetic is still 64 bit, unlike half width u128 limbs.
+    Chunk 0 is the integer part in 64 bit two's complement, and the other chunks are the fraction,
+    most significant first, the same layout as the half width numbers.  Only z^2 + c is supported;
+    the work areas of HPData<u64> are reused.
+*/
+
+use crate::{ HPData, periodicity_save };
+
+const NEG_TEST: u64 = 1 << 63;
+
+// chunks: 1 for the integral part, plus however many u64s are needed for the fractional part
+pub fn full_chunks(u32_chunks: usize) -> usize {
+    1 + (u32_chunks - 1).div_ceil(4)
+}
+
+// from the 16 bit chunks sent by the client
+pub fn u32_to_full(a: &[u32]) -> Vec<u64> {
+    let mut r = vec![a[0] as u16 as i16 as i64 as u64];
+    for c in a[1..].chunks(4) {
+        r.push(c.iter().enumerate().fold(0, | r, (k, &ck) | r | ((ck as u64) << (48 - k*16))));
+    }
+    r
+}
+
+// same as t_to_f64
+pub fn full_to_f64(x: &[u64]) -> f64 {
+    let neg = x[0] & NEG_TEST != 0;
+
+    // for a negative number, take the two's complement on the fly: complement every chunk
+    // above the lowest non-zero one, which instead becomes 2^64 - x[i]
+    let last = if neg { x.iter().rposition(| &xi | xi != 0).unwrap_or(0) } else { x.len() - 1 };
+    let scale = (2.0f64).powi(-64);
+    let mut r = 0.0;
+    for i in (0..=last).rev() {
+        let chunk = if !neg {
+            x[i] as f64
+        } else if i == last {
+            !x[i] as f64 + 1.0
+        } else {
+            !x[i] as f64
+        };
+        r = r*scale + chunk;
+    }
+    if neg { -r } else { r }
+}
+
+pub fn negate_full(x: &[u64], out: &mut [u64]) {
+    let mut carry = true;
+    for i in (0..out.len()).rev() {
+        (out[i], carry) = (!x[i]).overflowing_add(carry as u64);
+    }
+}
+
+pub fn incr_full(x: &mut [u64], dx: &[u64]) {
+    let mut carry = false;
+    for i in (0..x.len()).rev() {
+        (x[i], carry) = x[i].carrying_add(dx[i], carry);
+    }
+}
+
+pub fn add_full(x: &[u64], y: &[u64], out: &mut [u64]) {
+    let mut carry = false;
+    for i in (0..out.len()).rev() {
+        (out[i], carry) = x[i].carrying_add(y[i], carry);
+    }
+}
+
+// same as multiply
+pub fn multiply_full(x: &[u64], y: &[u64], work1: &mut [u64], work2: &mut [u64], out: &mut [u64]) {
+    let negx = x[0] & NEG_TEST != 0;
+    let negy = y[0] & NEG_TEST != 0;
+    if negx != negy {
+        if negx {
+            negate_full(x, work1);
+            multiply_pos_full(work1, y, work2);
+        } else {
+            negate_full(y, work1);
+            multiply_pos_full(x, work1, work2);
+        }
+        negate_full(work2, out);
+    } else if negx && negy {
+        negate_full(x, work1);
+        negate_full(y, work2);
+        multiply_pos_full(work1, work2, out);
+    } else {
+        multiply_pos_full(x, y, out);
+    }
+}
+
+pub fn sq_full(x: &[u64], work: &mut [u64], out: &mut [u64]) {
+    if x[0] & NEG_TEST != 0 {
+        negate_full(x, work);
+        sq_pos_full(work, out);
+    } else {
+        sq_pos_full(x, out);
+    }
+}
+
+// the product x[j]*y[i] lands in chunks i + j (low half) and i + j - 1 (high half); like multiply_pos,
+// the high halves of the products just past the end of out are kept and the rest are dropped
+fn multiply_pos_full(x: &[u64], y: &[u64], out: &mut [u64]) {
+    let count = out.len();
+    // lets the compiler drop the bounds checks
+    let (x, y) = (&x[..count], &y[..count]);
+    out.fill(0);
+    for j in 0..count {
+        let mut carry = if j > 0 { ((x[j] as u128*y[count - j] as u128) >> 64) as u64 } else { 0 };
+        let mut k = count - 1;
+        for i in (0..count - j).rev() {
+            let s = out[k] as u128 + x[j] as u128*y[i] as u128 + carry as u128;
+            (out[k], carry) = (s as u64, (s >> 64) as u64);
+            if k == 0 {
+                break;
+            }
+            k -= 1;
+        }
+        // the rest of the chunks above k
+        if j > 0 {
+            let mut k = j - 1;
+            while carry != 0 {
+                let overflow;
+                (out[k], overflow) = out[k].overflowing_add(carry);
+                carry = overflow as u64;
+                if k == 0 {
+                    break;
+                }
+                k -= 1;
+            }
+        }
+    }
+}
+
+// same as sq_pos: the same result as multiply_pos_full(x, x, out), computing each cross product once
+fn sq_pos_full(x: &[u64], out: &mut [u64]) {
+    let count = out.len();
+    let x = &x[..count];
+    out.fill(0);
+
+    // cross products x[j]*x[i], i > j, including the high halves of those just past the end of out
+    for j in 0..count {
+        let mut i = count - j;
+        if i <= j {
+            break;
+        }
+        let mut carry = if j > 0 { ((x[j] as u128*x[i] as u128) >> 64) as u64 } else { 0 };
+        let mut k = count - 1;
+        while i > j + 1 {
+            i -= 1;
+            let s = out[k] as u128 + x[j] as u128*x[i] as u128 + carry as u128;
+            (out[k], carry) = (s as u64, (s >> 64) as u64);
+            k -= 1;
+        }
+        while carry != 0 {
+            let overflow;
+            (out[k], overflow) = out[k].overflowing_add(carry);
+            carry = overflow as u64;
+            if k == 0 {
+                break;
+            }
+            k -= 1;
+        }
+    }
+
+    // double the cross products and add the diagonal, from the least significant chunk up;
+    // the carry can be a little over 64 bits
+    let mut carry = if count.is_multiple_of(2) { (x[count/2] as u128*x[count/2] as u128) >> 64 } else { 0 };
+    for k in (0..count).rev() {
+        let mut s = 2*out[k] as u128 + carry;
+        if k.is_multiple_of(2) {
+            let p = x[k/2] as u128*x[k/2] as u128;
+            s += p as u64 as u128;
+            carry = (s >> 64) + (p >> 64);
+        } else {
+            carry = s >> 64;
+        }
+        out[k] = s as u64;
+    }
+}
+
+// the bailout as the integer part and first fraction chunk of a full width number
+fn bailout_full(bailout: f64) -> (u64, u64) {
+    let int_part = bailout.floor();
+    (int_part as u64, ((bailout - int_part)*(2.0f64).powi(64)) as u64)
+}
+
+// same tests as in_main_cardioid_or_bulb_hp
+fn in_main_cardioid_or_bulb_full(hp_data: &mut HPData<u64>, x: &[u64], y: &[u64]) -> bool {
+    // work2 = y*y
+    sq_full(y, &mut hp_data.work3, &mut hp_data.work2);
+
+    // period 2 bulb: work4 = x + 1, work3 = (x + 1)^2 + y*y, which is < 1/16 if its top 4 fraction bits are 0
+    hp_data.work4.copy_from_slice(x);
+    hp_data.work4[0] = hp_data.work4[0].wrapping_add(1);
+    sq_full(&hp_data.work4, &mut hp_data.work3, &mut hp_data.work1);
+    add_full(&hp_data.work1, &hp_data.work2, &mut hp_data.work3);
+    if hp_data.work3[0] == 0 && hp_data.work3[1] >> 60 == 0 {
+        return true;
+    }
+
+    // main cardioid: work4 = x - 1/4
+    hp_data.work5.fill(0);
+    hp_data.work5[0] = u64::MAX;
+    hp_data.work5[1] = 3 << 62;
+    add_full(x, &hp_data.work5, &mut hp_data.work4);
+
+    sq_full(&hp_data.work4, &mut hp_data.work3, &mut hp_data.work1);
+    // work3 = q
+    add_full(&hp_data.work1, &hp_data.work2, &mut hp_data.work3);
+    add_full(&hp_data.work3, &hp_data.work4, &mut hp_data.work1);
+    // zy = q*(q + (x - 1/4)), zx = 4*zy
+    multiply_full(&hp_data.work3, &hp_data.work1, &mut hp_data.work4, &mut hp_data.zx, &mut hp_data.zy);
+    add_full(&hp_data.zy, &hp_data.zy, &mut hp_data.work1);
+    add_full(&hp_data.work1, &hp_data.work1, &mut hp_data.zx);
+    // inside if y*y - zx >= 0
+    negate_full(&hp_data.zx, &mut hp_data.work1);
+    add_full(&hp_data.work2, &hp_data.work1, &mut hp_data.work3);
+    hp_data.work3[0] & NEG_TEST == 0
+}
+
+// same as count_iterations_hp_smooth; hp_data must have been made with full_chunks
+pub fn count_iterations_full_smooth(hp_data: &mut HPData<u64>, x: &[u64], y: &[u64], max_iterations: i32, bailout: f64) -> (i32, f64) {
+    if in_main_cardioid_or_bulb_full(hp_data, x, y) {
+        return (-1, 0.0);
+    }
+
+    let mut count = 0;
+    hp_data.zx.copy_from_slice(x);
+    hp_data.zy.copy_from_slice(y);
+    hp_data.check_zx.copy_from_slice(x);
+    hp_data.check_zy.copy_from_slice(y);
+
+    let bailout = bailout_full(bailout);
+
+    // while count < max_iterations && zx*zx + zy*zy < bailout {
+    while count < max_iterations {
+        sq_full(&hp_data.zx, &mut hp_data.work3, &mut hp_data.work1);
+        sq_full(&hp_data.zy, &mut hp_data.work3, &mut hp_data.work2);
+        add_full(&hp_data.work1, &hp_data.work2, &mut hp_data.work3);
+        if hp_data.work3[0] > bailout.0 || (hp_data.work3[0] == bailout.0 && hp_data.work3[1] >= bailout.1) {
+            return (count, full_to_f64(&hp_data.work3));
+        }
+
+        add_full(&hp_data.zx, &hp_data.zx, &mut hp_data.work4);
+
+        // zx = zx*zx - zy*zy + x;
+        negate_full(&hp_data.work2, &mut hp_data.work3);
+        add_full(&hp_data.work1, &hp_data.work3, &mut hp_data.work2);
+        add_full(&hp_data.work2, x, &mut hp_data.zx);
+
+        // zy = 2.0*zx*zy + y;
+        multiply_full(&hp_data.work4, &hp_data.zy, &mut hp_data.work1, &mut hp_data.work3, &mut hp_data.work2);
+        add_full(&hp_data.work2, y, &mut hp_data.zy);
+        count += 1;
+
+        if hp_data.periodicity {
+            if hp_data.zx == hp_data.check_zx && hp_data.zy == hp_data.check_zy {
+                return (-1, 0.0);
+            }
+            if periodicity_save(count) {
+                hp_data.check_zx.copy_from_slice(&hp_data.zx);
+                hp_data.check_zy.copy_from_slice(&hp_data.zy);
+            }
+        }
+    }
+    (-1, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ HPData, DEFAULT_BAILOUT, count_iterations_hp_smooth };
+    use crate::test_grid::{ grid_hp, MAX_ITERATIONS };
+    use super::{ full_chunks, u32_to_full, count_iterations_full_smooth };
+
+    // client chunk counts: 5 and 9 fill their full width chunks, 7 and 10 round up to another one
+    const U32_CHUNKS: [usize; 4] = [5, 7, 9, 10];
+
+    // the products are truncated a full width chunk at a time rather than 16 bits at a time, which
+    // shows in the final values with only one chunk of fraction
+    const TOLERANCE: f64 = 1.0e-5;
+
+    #[test]
+    fn full_smooth_matches_hp() {
+        for u32_chunks in U32_CHUNKS {
+            let chunks = full_chunks(u32_chunks);
+            let mut full_data = HPData::<u64>::new(chunks);
+            // the half width numbers with as many bits of fraction as the full width ones
+            let half_chunks = 1 + 4*(chunks - 1);
+            let mut hp_data = HPData::<u32>::new(half_chunks);
+            for (mut x, mut y) in grid_hp::<u32>(u32_chunks) {
+                let (x_full, y_full) = (u32_to_full(&x), u32_to_full(&y));
+                assert_eq!(x_full.len(), chunks);
+                x.resize(half_chunks, 0);
+                y.resize(half_chunks, 0);
+                let expected = count_iterations_hp_smooth(&mut hp_data, &x, &y, MAX_ITERATIONS, DEFAULT_BAILOUT);
+                let actual = count_iterations_full_smooth(&mut full_data, &x_full, &y_full, MAX_ITERATIONS, DEFAULT_BAILOUT);
+                assert!(expected.0 == actual.0 && (expected.1 - actual.1).abs() <= TOLERANCE*expected.1,
+                    "u32 chunks = {}: {:?} and {:?} differ", u32_chunks, expected, actual);
+            }
+        }
+    }
+}
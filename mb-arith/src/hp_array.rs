@@ -0,0 +1,307 @@
+/*
+    Fixed size high precision numbers
+    By Bill Wood
+
+    The high precision functions in lib.rs work on slices, whose length is only known at run time,
+    so they use get_unchecked to avoid a bounds check on every chunk.  These versions work on
+    [T; N] arrays instead: with N known at compile time every index is checked for free, the numbers
+    live on the stack rather than in the Vecs of HPData, and the loops can be unrolled for each N
+    they're compiled for.  They do the same operations in the same order as the slice versions, so
+    the results are identical.
+    Karatsuba multiplication isn't used, since it only pays off far above the chunk counts these are
+    meant for; callers should stick to the slice versions at or above KARATSUBA_THRESHOLD.
+*/
+
+use core::array::from_fn;
+use crate::{ Limb, t_to_f64, bailout_chunks, periodicity_save };
+
+// same as negate
+#[inline]
+pub fn negate_n<T, const N: usize>(x: &[T; N]) -> [T; N]
+where T: Limb,
+{
+    let t_low_bits = T::LOW_BITS;
+    let mut out: [T; N] = from_fn(| i | t_low_bits - x[i]);
+
+    let mut i = N - 1;
+    out[i] += T::one();
+    let t_overflow_test = t_low_bits + T::one();
+    while i > 0 && out[i] & t_overflow_test != T::zero() {
+        out[i] &= t_low_bits;
+        out[i - 1] += T::one();
+        i -= 1;
+    }
+    out[0] &= t_low_bits;
+    out
+}
+
+// same as add
+#[inline]
+pub fn add_n<T, const N: usize>(x: &[T; N], y: &[T; N]) -> [T; N]
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let mut out = [T::zero(); N];
+    let mut carry = T::zero();
+    for i in (0..N).rev() {
+        let s = x[i] + y[i] + carry;
+        carry = s >> t_half_bits;
+        out[i] = s & t_low_bits;
+    }
+    out
+}
+
+// same as multiply
+#[inline]
+pub fn multiply_n<T, const N: usize>(x: &[T; N], y: &[T; N]) -> [T; N]
+where T: Limb,
+{
+    let t_low_bits = T::LOW_BITS;
+    let t_neg_test = (t_low_bits + T::one()) >> 1;
+
+    let negx = x[0] & t_neg_test != T::zero();
+    let negy = y[0] & t_neg_test != T::zero();
+    let x = if negx { negate_n(x) } else { *x };
+    let y = if negy { negate_n(y) } else { *y };
+    let out = multiply_pos_n(&x, &y);
+    if negx != negy { negate_n(&out) } else { out }
+}
+
+// same as multiply_pos, without Karatsuba
+#[inline]
+pub fn multiply_pos_n<T, const N: usize>(x: &[T; N], y: &[T; N]) -> [T; N]
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let mut out = [T::zero(); N];
+
+    let x0 = x[0];
+    if x0 != T::zero() {
+        let mut carry = T::zero();
+        for i in (0..N).rev() {
+            let s = x0*y[i] + carry;
+            carry = s >> t_half_bits;
+            out[i] = s & t_low_bits;
+        }
+    }
+
+    for (j, &xj) in x.iter().enumerate().skip(1) {
+        let mut i = N - j;
+        let mut carry = (xj*y[i]) >> t_half_bits;
+        let mut k = N - 1;
+        while i > 0 {
+            i -= 1;
+            let s = out[k] + xj*y[i] + carry;
+            carry = s >> t_half_bits;
+            out[k] = s & t_low_bits;
+            k -= 1;
+        }
+        while carry != T::zero() {
+            let s = out[k] + carry;
+            carry = s >> t_half_bits;
+            out[k] = s & t_low_bits;
+            if k == 0 {
+                break;
+            }
+            k -= 1;
+        }
+    }
+    out
+}
+
+// same as sq
+#[inline]
+pub fn sq_n<T, const N: usize>(x: &[T; N]) -> [T; N]
+where T: Limb,
+{
+    let t_low_bits = T::LOW_BITS;
+    let t_neg_test = (t_low_bits + T::one()) >> 1;
+    if x[0] & t_neg_test != T::zero() {
+        sq_pos_n(&negate_n(x))
+    } else {
+        sq_pos_n(x)
+    }
+}
+
+// same as sq_pos, without Karatsuba
+#[inline]
+fn sq_pos_n<T, const N: usize>(x: &[T; N]) -> [T; N]
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let mut out = [T::zero(); N];
+
+    // cross products x[j]*x[i], i > j, including the high halves of those just past the end of out
+    for j in 0..N {
+        let mut i = N - j;
+        if i <= j {
+            break;
+        }
+        let mut carry = if j > 0 { (x[j]*x[i]) >> t_half_bits } else { T::zero() };
+        let mut k = N - 1;
+        while i > j + 1 {
+            i -= 1;
+            let s = out[k] + x[j]*x[i] + carry;
+            carry = s >> t_half_bits;
+            out[k] = s & t_low_bits;
+            k -= 1;
+        }
+        while carry != T::zero() {
+            let s = out[k] + carry;
+            carry = s >> t_half_bits;
+            out[k] = s & t_low_bits;
+            if k == 0 {
+                break;
+            }
+            k -= 1;
+        }
+    }
+
+    // double the cross products and add the diagonal, from the least significant chunk up
+    let mut carry = if N.is_multiple_of(2) { (x[N/2]*x[N/2]) >> t_half_bits } else { T::zero() };
+    for k in (0..N).rev() {
+        let mut s = out[k] + out[k] + carry;
+        if k.is_multiple_of(2) {
+            let p = x[k/2]*x[k/2];
+            s += p & t_low_bits;
+            carry = (s >> t_half_bits) + (p >> t_half_bits);
+        } else {
+            carry = s >> t_half_bits;
+        }
+        out[k] = s & t_low_bits;
+    }
+    out
+}
+
+// same as escaped_hp
+#[inline]
+fn escaped_n<T, const N: usize>(norm: &[T; N], bailout: (T, T)) -> bool
+where T: Limb,
+{
+    norm[0] > bailout.0 || (norm[0] == bailout.0 && norm[1] >= bailout.1)
+}
+
+// same as in_main_cardioid_or_bulb_hp
+pub fn in_main_cardioid_or_bulb_n<T, const N: usize>(x: &[T; N], y: &[T; N]) -> bool
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let t_neg_test = (t_low_bits + T::one()) >> 1;
+    let y2 = sq_n(y);
+
+    // period 2 bulb: (x + 1)^2 + y*y < 1/16
+    let mut x1 = *x;
+    x1[0] = (x1[0] + T::one()) & t_low_bits;
+    let r = add_n(&sq_n(&x1), &y2);
+    if r[0] == T::zero() && r[1] >> (t_half_bits - 4) == T::zero() {
+        return true;
+    }
+
+    // main cardioid: x - 1/4, computed as x - 1 + 3/4
+    let mut x4 = *x;
+    let s = x4[1] + (t_low_bits - (t_low_bits >> 2));
+    x4[1] = s & t_low_bits;
+    x4[0] = (x4[0] + (s >> t_half_bits) + t_low_bits) & t_low_bits;
+
+    // inside if y*y - 4*q*(q + (x - 1/4)) >= 0
+    let q = add_n(&sq_n(&x4), &y2);
+    let p = multiply_n(&q, &add_n(&q, &x4));
+    let p2 = add_n(&p, &p);
+    let p4 = add_n(&p2, &p2);
+    add_n(&y2, &negate_n(&p4))[0] & t_neg_test == T::zero()
+}
+
+// same as count_iterations_hp
+pub fn count_iterations_hp_n<T, const N: usize>(x: &[T; N], y: &[T; N], max_iterations: i32, bailout: f64, periodicity: bool) -> i32
+where T: Limb,
+{
+    count_iterations_hp_smooth_n(x, y, max_iterations, bailout, periodicity).0
+}
+
+// same as count_iterations_hp_smooth
+pub fn count_iterations_hp_smooth_n<T, const N: usize>(x: &[T; N], y: &[T; N], max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64)
+where T: Limb,
+{
+    if in_main_cardioid_or_bulb_n(x, y) {
+        return (-1, 0.0);
+    }
+
+    let mut count = 0;
+    let (mut zx, mut zy) = (*x, *y);
+    let (mut check_zx, mut check_zy) = (zx, zy);
+
+    let bailout = bailout_chunks(bailout);
+
+    // while count < max_iterations && zx*zx + zy*zy < bailout {
+    while count < max_iterations {
+        let zx2 = sq_n(&zx);
+        let zy2 = sq_n(&zy);
+        let norm = add_n(&zx2, &zy2);
+        if escaped_n(&norm, bailout) {
+            return (count, t_to_f64(&norm));
+        }
+
+        // zx = zx*zx - zy*zy + x; zy = 2.0*zx*zy + y
+        let zx_2 = add_n(&zx, &zx);
+        zx = add_n(&add_n(&zx2, &negate_n(&zy2)), x);
+        zy = add_n(&multiply_n(&zx_2, &zy), y);
+        count += 1;
+
+        if periodicity {
+            if zx == check_zx && zy == check_zy {
+                return (-1, 0.0);
+            }
+            if periodicity_save(count) {
+                (check_zx, check_zy) = (zx, zy);
+            }
+        }
+    }
+    (-1, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ Limb, HPData, DEFAULT_BAILOUT, count_iterations_hp_smooth };
+    use crate::test_grid::{ grid_hp, MAX_ITERATIONS };
+    use super::count_iterations_hp_smooth_n;
+
+    fn smooth_n_matches_hp<T, const N: usize>()
+    where T: Limb,
+    {
+        let mut hp_data = HPData::<T>::new(N);
+        for (x, y) in grid_hp::<T>(N) {
+            let expected = count_iterations_hp_smooth(&mut hp_data, &x, &y, MAX_ITERATIONS, DEFAULT_BAILOUT);
+            let actual = count_iterations_hp_smooth_n::<T, N>(x.as_slice().try_into().unwrap(), y.as_slice().try_into().unwrap(),
+                MAX_ITERATIONS, DEFAULT_BAILOUT, true);
+            assert_eq!(expected, actual, "N = {}", N);
+        }
+    }
+
+    // every N the server dispatches to
+    fn smooth_n_matches_hp_all<T>()
+    where T: Limb,
+    {
+        smooth_n_matches_hp::<T, 2>();
+        smooth_n_matches_hp::<T, 3>();
+        smooth_n_matches_hp::<T, 4>();
+        smooth_n_matches_hp::<T, 5>();
+        smooth_n_matches_hp::<T, 6>();
+        smooth_n_matches_hp::<T, 7>();
+        smooth_n_matches_hp::<T, 8>();
+    }
+
+    #[test]
+    fn smooth_n_matches_hp_u32() {
+        smooth_n_matches_hp_all::<u32>();
+    }
+
+    #[test]
+    fn smooth_n_matches_hp_u64() {
+        smooth_n_matches_hp_all::<u64>();
+    }
+
+    #[test]
+    fn smooth_n_matches_hp_u128() {
+        smooth_n_matches_hp_all::<u128>();
+    }
+}
@@ -0,0 +1,440 @@
+/*
+    Batched high precision kernel
+    By Bill Wood
+
+    count_iterations_hp_smooth_batch iterates a batch of pixels in lockstep.  The numbers are laid
+    out structure of arrays, limb by limb, with the pixels of the batch side by side, so every loop
+    runs across the pixels and the compiler can vectorize it.  The carries are kept per pixel too,
+    and the sign of each pixel's numbers is handled with masks instead of branches.
+    Products are summed a column at a time, half limb products and all, rather than a row at a time
+    like multiply_pos, so there are no data dependent carry loops.  The sum is exact either way, so
+    the results are identical to count_iterations_hp_smooth's.
+    A pixel that escapes, or whose orbit repeats, is swapped with the last pixel of the batch and the
+    batch shrinks by one, so the loops only run over the pixels still iterating.  Points in the
+    main cardioid or period 2 bulb never join the batch.
+*/
+
+use crate::{ Limb, HPData, Mandelbrot, t_to_f64, bailout_chunks, in_main_cardioid_or_bulb_hp, continue_iterations_hp_formula,
+    periodicity_save };
+
+// with fewer pixels than this left, the batch loops cost more than they save, so the rest of the
+// pixels are finished one at a time
+const MIN_BATCH: usize = 8;
+
+// the numbers of a batch, limb i of pixel p at [i*capacity + p]
+pub struct HPBatch<T> {
+    chunks: usize,
+    capacity: usize,
+    // pixels pushed, and pixels still iterating
+    len: usize,
+    active: usize,
+    x: Vec<T>,
+    y: Vec<T>,
+    zx: Vec<T>,
+    zy: Vec<T>,
+    check_zx: Vec<T>,
+    check_zy: Vec<T>,
+    work1: Vec<T>,
+    work2: Vec<T>,
+    work3: Vec<T>,
+    work4: Vec<T>,
+    work5: Vec<T>,
+    // per pixel carries, sums and sign masks
+    carry: Vec<T>,
+    sum: Vec<T>,
+    mask: Vec<T>,
+    mask2: Vec<T>,
+    // the index each iterating pixel was pushed at
+    pixel: Vec<usize>,
+    results: Vec<(i32, f64)>,
+    // for the main cardioid and period 2 bulb tests and for finish, with the periodicity checking setting
+    hp_data: HPData<T>,
+}
+
+impl<T> HPBatch<T>
+where T: Limb,
+{
+    // with periodicity checking
+    pub fn new(chunks: usize, capacity: usize) -> HPBatch<T> {
+        HPBatch::with_periodicity(chunks, capacity, true)
+    }
+
+    pub fn with_periodicity(chunks: usize, capacity: usize, periodicity: bool) -> HPBatch<T> {
+        let numbers = || vec![T::zero(); chunks*capacity];
+        HPBatch {
+            chunks,
+            capacity,
+            len: 0,
+            active: 0,
+            x: numbers(),
+            y: numbers(),
+            zx: numbers(),
+            zy: numbers(),
+            check_zx: numbers(),
+            check_zy: numbers(),
+            work1: numbers(),
+            work2: numbers(),
+            work3: numbers(),
+            work4: numbers(),
+            work5: numbers(),
+            carry: vec![T::zero(); capacity],
+            sum: vec![T::zero(); capacity],
+            mask: vec![T::zero(); capacity],
+            mask2: vec![T::zero(); capacity],
+            pixel: vec![0; capacity],
+            results: vec![(-1, 0.0); capacity],
+            hp_data: HPData::with_periodicity(chunks, periodicity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // empty the batch for the next set of pixels
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.active = 0;
+    }
+
+    // add the point x, y of chunks chunks to the batch; its result will be at the index it's pushed at
+    pub fn push(&mut self, x: &[T], y: &[T]) {
+        assert!(self.len < self.capacity, "HPBatch is full");
+        self.results[self.len] = (-1, 0.0);
+        if !in_main_cardioid_or_bulb_hp(&mut self.hp_data, x, y) {
+            let (p, cap) = (self.active, self.capacity);
+            for i in 0..self.chunks {
+                self.x[i*cap + p] = x[i];
+                self.y[i*cap + p] = y[i];
+            }
+            self.pixel[p] = self.len;
+            self.active += 1;
+        }
+        self.len += 1;
+    }
+
+    // move pixel from to pixel to in every number that's live across iterations
+    fn move_pixel(&mut self, from: usize, to: usize) {
+        let cap = self.capacity;
+        for v in [&mut self.x, &mut self.y, &mut self.zx, &mut self.zy, &mut self.check_zx, &mut self.check_zy, &mut self.work1, &mut self.work2] {
+            for i in 0..self.chunks {
+                v[i*cap + to] = v[i*cap + from];
+            }
+        }
+        self.pixel[to] = self.pixel[from];
+    }
+
+    // drop pixel p from the iterating pixels, recording its result
+    fn retire(&mut self, p: usize, result: (i32, f64)) {
+        self.results[self.pixel[p]] = result;
+        self.active -= 1;
+        if p != self.active {
+            self.move_pixel(self.active, p);
+        }
+    }
+
+    // pixel p of v as a high precision number
+    fn gather(&self, v: &[T], p: usize) -> Vec<T> {
+        let mut out = vec![T::zero(); self.chunks];
+        gather_into(v, self.capacity, p, &mut out);
+        out
+    }
+
+    // iterate the pixels still in the batch one at a time from iteration count on
+    fn finish(&mut self, count: i32, max_iterations: i32, bailout: f64) {
+        let cap = self.capacity;
+        while self.active > 0 {
+            let p = self.active - 1;
+            let (x, y) = (self.gather(&self.x, p), self.gather(&self.y, p));
+            gather_into(&self.zx, cap, p, &mut self.hp_data.zx);
+            gather_into(&self.zy, cap, p, &mut self.hp_data.zy);
+            gather_into(&self.check_zx, cap, p, &mut self.hp_data.check_zx);
+            gather_into(&self.check_zy, cap, p, &mut self.hp_data.check_zy);
+            // continue_iterations_hp_formula leaves zx*zx + zy*zy in work3 when a point escapes
+            let count = continue_iterations_hp_formula(&Mandelbrot, &mut self.hp_data, &x, &y, count, max_iterations, bailout);
+            let result = if count < 0 { (count, 0.0) } else { (count, t_to_f64(&self.hp_data.work3)) };
+            self.retire(p, result);
+        }
+    }
+}
+
+// out = pixel p of v, a batch of capacity cap
+fn gather_into<T>(v: &[T], cap: usize, p: usize, out: &mut [T])
+where T: Limb,
+{
+    for (i, out) in out.iter_mut().enumerate() {
+        *out = v[i*cap + p];
+    }
+}
+
+// out = x + y for the first n pixels
+fn add_batch<T>(chunks: usize, cap: usize, n: usize, x: &[T], y: &[T], out: &mut [T], carry: &mut [T])
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let carry = &mut carry[..n];
+    carry.fill(T::zero());
+    for i in (0..chunks).rev() {
+        let r = i*cap..i*cap + n;
+        for (((&x, &y), out), carry) in x[r.clone()].iter().zip(&y[r.clone()]).zip(&mut out[r]).zip(carry.iter_mut()) {
+            let s = x + y + *carry;
+            *carry = s >> t_half_bits;
+            *out = s & t_low_bits;
+        }
+    }
+}
+
+// mask = t_low_bits for the pixels of x that are negative, otherwise 0
+fn sign_mask_batch<T>(n: usize, x: &[T], mask: &mut [T])
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    for (&x, mask) in x[..n].iter().zip(&mut mask[..n]) {
+        *mask = ((x >> (t_half_bits - 1)) & T::one())*t_low_bits;
+    }
+}
+
+// out = -x for the pixels where mask is t_low_bits, otherwise x
+fn negate_masked_batch<T>(chunks: usize, cap: usize, n: usize, x: &[T], mask: &[T], out: &mut [T], carry: &mut [T])
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let (mask, carry) = (&mask[..n], &mut carry[..n]);
+    for (&mask, carry) in mask.iter().zip(carry.iter_mut()) {
+        *carry = mask & T::one();
+    }
+    for i in (0..chunks).rev() {
+        let r = i*cap..i*cap + n;
+        for (((&x, out), &mask), carry) in x[r.clone()].iter().zip(&mut out[r]).zip(mask).zip(carry.iter_mut()) {
+            let s = (x ^ mask) + *carry;
+            *carry = s >> t_half_bits;
+            *out = s & t_low_bits;
+        }
+    }
+}
+
+// sum += the low halves of x[i]*y[j], or the high halves if high
+#[inline]
+fn add_products<T>(x: &[T], y: &[T], sum: &mut [T], high: bool)
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    if high {
+        for ((&x, &y), sum) in x.iter().zip(y).zip(sum.iter_mut()) {
+            *sum += (x*y) >> t_half_bits;
+        }
+    } else {
+        for ((&x, &y), sum) in x.iter().zip(y).zip(sum.iter_mut()) {
+            *sum += (x*y) & t_low_bits;
+        }
+    }
+}
+
+// out = x*y for the first n pixels, x and y >= 0; chunk k of out sums the low halves of the products
+// x[i]*y[j] with i + j == k and the high halves of those with i + j == k + 1, like multiply_pos
+#[allow(clippy::too_many_arguments)]
+fn multiply_pos_batch<T>(chunks: usize, cap: usize, n: usize, x: &[T], y: &[T], out: &mut [T], carry: &mut [T], sum: &mut [T])
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let (carry, sum) = (&mut carry[..n], &mut sum[..n]);
+    carry.fill(T::zero());
+    let limb = | i: usize | i*cap..i*cap + n;
+    for k in (0..chunks).rev() {
+        sum.copy_from_slice(carry);
+        for i in 0..=k {
+            add_products(&x[limb(i)], &y[limb(k - i)], sum, false);
+        }
+        for i in (k + 2).saturating_sub(chunks)..=(k + 1).min(chunks - 1) {
+            add_products(&x[limb(i)], &y[limb(k + 1 - i)], sum, true);
+        }
+        for ((out, carry), &sum) in out[limb(k)].iter_mut().zip(carry.iter_mut()).zip(sum.iter()) {
+            *carry = sum >> t_half_bits;
+            *out = sum & t_low_bits;
+        }
+    }
+}
+
+// out = x*x for the first n pixels, x >= 0; the same sums as multiply_pos_batch, with each cross
+// product computed once and added twice
+fn sq_pos_batch<T>(chunks: usize, cap: usize, n: usize, x: &[T], out: &mut [T], carry: &mut [T], sum: &mut [T])
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let (carry, sum) = (&mut carry[..n], &mut sum[..n]);
+    carry.fill(T::zero());
+    let limb = | i: usize | i*cap..i*cap + n;
+    for k in (0..chunks).rev() {
+        sum.copy_from_slice(carry);
+        // low halves of the products with i + j == k, then high halves of those with i + j == k + 1
+        for (s, high) in [(k, false), (k + 1, true)] {
+            for i in s.saturating_sub(chunks - 1)..=s/2 {
+                let j = s - i;
+                add_products(&x[limb(i)], &x[limb(j)], sum, high);
+                if i != j {
+                    add_products(&x[limb(i)], &x[limb(j)], sum, high);
+                }
+            }
+        }
+        for ((out, carry), &sum) in out[limb(k)].iter_mut().zip(carry.iter_mut()).zip(sum.iter()) {
+            *carry = sum >> t_half_bits;
+            *out = sum & t_low_bits;
+        }
+    }
+}
+
+// same as count_iterations_hp_smooth for every pixel of the batch; the results are in the order the
+// pixels were pushed
+pub fn count_iterations_hp_smooth_batch<T>(batch: &mut HPBatch<T>, max_iterations: i32, bailout: f64) -> &[(i32, f64)]
+where T: Limb,
+{
+    let (chunks, cap) = (batch.chunks, batch.capacity);
+    for v in [&mut batch.zx, &mut batch.check_zx] {
+        v.copy_from_slice(&batch.x);
+    }
+    for v in [&mut batch.zy, &mut batch.check_zy] {
+        v.copy_from_slice(&batch.y);
+    }
+    let periodicity = batch.hp_data.periodicity;
+    let bailout_f64 = bailout;
+    let bailout = bailout_chunks::<T>(bailout);
+
+    let mut count = 0;
+    while count < max_iterations && batch.active > 0 {
+        if batch.active < MIN_BATCH {
+            batch.finish(count, max_iterations, bailout_f64);
+            break;
+        }
+        let b = &mut *batch;
+        let n = b.active;
+
+        // work1 = zx*zx, work2 = zy*zy, work3 = zx*zx + zy*zy
+        sign_mask_batch(n, &b.zx, &mut b.mask);
+        negate_masked_batch(chunks, cap, n, &b.zx, &b.mask, &mut b.work3, &mut b.carry);
+        sq_pos_batch(chunks, cap, n, &b.work3, &mut b.work1, &mut b.carry, &mut b.sum);
+        sign_mask_batch(n, &b.zy, &mut b.mask);
+        negate_masked_batch(chunks, cap, n, &b.zy, &b.mask, &mut b.work3, &mut b.carry);
+        sq_pos_batch(chunks, cap, n, &b.work3, &mut b.work2, &mut b.carry, &mut b.sum);
+        add_batch(chunks, cap, n, &b.work1, &b.work2, &mut b.work3, &mut b.carry);
+
+        // retire the escaped pixels, from the end so the pixels moved into their places have been tested
+        for p in (0..n).rev() {
+            // a norm of just the integer chunk has a zero fraction, as in escaped_hp
+            let (norm0, norm1) = (b.work3[p], if chunks > 1 { b.work3[cap + p] } else { T::zero() });
+            if norm0 > bailout.0 || (norm0 == bailout.0 && norm1 >= bailout.1) {
+                let norm = t_to_f64(&b.gather(&b.work3, p));
+                b.retire(p, (count, norm));
+            }
+        }
+        let n = b.active;
+        if n == 0 {
+            break;
+        }
+
+        // work4 = 2*zx
+        add_batch(chunks, cap, n, &b.zx, &b.zx, &mut b.work4, &mut b.carry);
+
+        // zx = zx*zx - zy*zy + x
+        b.mask[..n].fill(T::LOW_BITS);
+        negate_masked_batch(chunks, cap, n, &b.work2, &b.mask, &mut b.work3, &mut b.carry);
+        add_batch(chunks, cap, n, &b.work1, &b.work3, &mut b.work5, &mut b.carry);
+        add_batch(chunks, cap, n, &b.work5, &b.x, &mut b.zx, &mut b.carry);
+
+        // zy = 2*zx*zy + y, with the sign of the product the exclusive or of the signs of 2*zx and zy
+        sign_mask_batch(n, &b.work4, &mut b.mask);
+        sign_mask_batch(n, &b.zy, &mut b.mask2);
+        negate_masked_batch(chunks, cap, n, &b.work4, &b.mask, &mut b.work1, &mut b.carry);
+        negate_masked_batch(chunks, cap, n, &b.zy, &b.mask2, &mut b.work2, &mut b.carry);
+        multiply_pos_batch(chunks, cap, n, &b.work1, &b.work2, &mut b.work3, &mut b.carry, &mut b.sum);
+        for (mask, &mask2) in b.mask[..n].iter_mut().zip(&b.mask2[..n]) {
+            *mask = *mask ^ mask2;
+        }
+        negate_masked_batch(chunks, cap, n, &b.work3, &b.mask, &mut b.work5, &mut b.carry);
+        add_batch(chunks, cap, n, &b.work5, &b.y, &mut b.zy, &mut b.carry);
+        count += 1;
+
+        if periodicity {
+            // sum = the bits that differ between z and the saved z, for each pixel
+            let sum = &mut b.sum[..n];
+            sum.fill(T::zero());
+            for i in 0..chunks {
+                let r = i*cap..i*cap + n;
+                for ((((sum, &zx), &zy), &check_zx), &check_zy) in sum.iter_mut().zip(&b.zx[r.clone()]).zip(&b.zy[r.clone()]).zip(&b.check_zx[r.clone()]).zip(&b.check_zy[r]) {
+                    *sum |= zx ^ check_zx;
+                    *sum |= zy ^ check_zy;
+                }
+            }
+            for p in (0..n).rev() {
+                if b.sum[p] == T::zero() {
+                    b.retire(p, (-1, 0.0));
+                }
+            }
+            if periodicity_save(count) {
+                b.check_zx.copy_from_slice(&b.zx);
+                b.check_zy.copy_from_slice(&b.zy);
+            }
+        }
+    }
+    &batch.results[..batch.len]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ Limb, HPData, DEFAULT_BAILOUT, count_iterations_hp_smooth };
+    use crate::test_grid::{ grid_hp, COLUMNS, MAX_ITERATIONS };
+    use super::{ HPBatch, MIN_BATCH, count_iterations_hp_smooth_batch };
+
+    // batches of size pixels at a time, reusing the batch, against count_iterations_hp_smooth
+    fn batch_matches_hp<T>(chunks: usize, size: usize)
+    where T: Limb,
+    {
+        let mut hp_data = HPData::<T>::new(chunks);
+        let mut batch = HPBatch::<T>::new(chunks, size);
+        let points: Vec<_> = grid_hp::<T>(chunks).collect();
+        for points in points.chunks(size) {
+            batch.clear();
+            for (x, y) in points {
+                batch.push(x, y);
+            }
+            let results = count_iterations_hp_smooth_batch(&mut batch, MAX_ITERATIONS, DEFAULT_BAILOUT);
+            assert_eq!(results.len(), points.len());
+            for ((x, y), &actual) in points.iter().zip(results) {
+                assert_eq!(count_iterations_hp_smooth(&mut hp_data, x, y, MAX_ITERATIONS, DEFAULT_BAILOUT), actual,
+                    "chunks = {}, batch size = {}", chunks, size);
+            }
+        }
+    }
+
+    // a batch too short for the batch loops, which goes straight to finish, and a row of the grid
+    fn batch_matches_hp_all<T>()
+    where T: Limb,
+    {
+        for chunks in [1, 3, 7] {
+            batch_matches_hp::<T>(chunks, MIN_BATCH - 1);
+            batch_matches_hp::<T>(chunks, COLUMNS);
+        }
+    }
+
+    #[test]
+    fn batch_matches_hp_u32() {
+        batch_matches_hp_all::<u32>();
+    }
+
+    #[test]
+    fn batch_matches_hp_u64() {
+        batch_matches_hp_all::<u64>();
+    }
+
+    #[test]
+    fn batch_matches_hp_u128() {
+        batch_matches_hp_all::<u128>();
+    }
+}
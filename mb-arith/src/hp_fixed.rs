@@ -0,0 +1,573 @@
+/*
+    Typed high precision fixed point numbers
+    By Bill Wood
+
+    An HpFixed wraps the chunks used by the high precision arithmetic: chunk 0 is the integer
+    part, in two's complement with the sign at the top of its T::HALF_BITS bits, and each of
+    the following chunks holds T::HALF_BITS more bits of fraction.  Values with different
+    numbers of chunks can be mixed; the shorter one is extended with zero chunks.
+
+    Decimal strings are converted the same way as convert() in MB.html: the magnitude is
+    truncated to the number of chunks, then negated if need be, so the chunks match what the
+    browser sends to the server.
+*/
+
+use std::ops::{ Add, Sub, Mul, Neg };
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+use num::traits::AsPrimitive;
+use core::cmp::PartialEq;
+use crate::{ Limb, add, negate, multiply, u32_to_t, t_to_f64, t_to_floatexp, FloatExp, DoubleDouble };
+
+#[derive(Clone, Debug)]
+pub struct HpFixed<T> {
+    chunks: Vec<T>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseHpFixedError(&'static str);
+
+impl fmt::Display for ParseHpFixedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl std::error::Error for ParseHpFixedError {}
+
+// two's complement of chunks holding half_bits bits each
+fn negate_u64(x: &mut [u64], half_bits: usize) {
+    let low_bits = u64::MAX >> (64 - half_bits);
+    let mut carry = 1;
+    for xi in x.iter_mut().rev() {
+        let v = (low_bits - *xi) as u128 + carry;
+        *xi = v as u64 & low_bits;
+        carry = v >> half_bits;
+    }
+}
+
+// the most digits, and the largest exponent, a decimal string can have; far more than any view needs,
+// and small enough that converting it, which takes time proportional to digits*chunks, stays quick
+pub const MAX_DECIMAL_DIGITS: usize = 4096;
+
+// a decimal string split into its sign and digits, with the decimal point applied
+struct Decimal {
+    neg: bool,
+    int_digits: Vec<u8>,
+    frac_digits: Vec<u8>,
+}
+
+fn parse_decimal(s: &str) -> Result<Decimal, ParseHpFixedError> {
+    let s = s.trim();
+    let (neg, s) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let (mantissa, exp) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], s[i + 1..].parse::<i64>().map_err(|_| ParseHpFixedError("invalid exponent"))?),
+        None => (s, 0),
+    };
+    if exp.unsigned_abs() > MAX_DECIMAL_DIGITS as u64 {
+        return Err(ParseHpFixedError("exponent out of range"));
+    }
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if int_part.is_empty() && frac_part.is_empty() {
+        return Err(ParseHpFixedError("no digits"));
+    }
+    if int_part.len() + frac_part.len() > MAX_DECIMAL_DIGITS {
+        return Err(ParseHpFixedError("too many digits"));
+    }
+    let mut digits = Vec::with_capacity(int_part.len() + frac_part.len());
+    for c in int_part.bytes().chain(frac_part.bytes()) {
+        if !c.is_ascii_digit() {
+            return Err(ParseHpFixedError("invalid digit"));
+        }
+        digits.push(c - b'0');
+    }
+
+    // move the decimal point by the exponent, padding with zeros as needed
+    let point = (int_part.len() as i64).checked_add(exp).ok_or(ParseHpFixedError("exponent out of range"))?;
+    if point < 0 {
+        let mut frac_digits = vec![0; (-point) as usize];
+        frac_digits.extend(digits);
+        return Ok(Decimal { neg, int_digits: vec![], frac_digits });
+    }
+    let point = point as usize;
+    if point > digits.len() {
+        digits.resize(point, 0);
+    }
+    let frac_digits = digits.split_off(point);
+    Ok(Decimal { neg, int_digits: digits, frac_digits })
+}
+
+#[allow(clippy::len_without_is_empty, reason = "there's always at least one chunk")]
+impl<T> HpFixed<T>
+where T: Limb,
+{
+    pub fn from_chunks(chunks: Vec<T>) -> HpFixed<T> {
+        assert!(!chunks.is_empty());
+        HpFixed { chunks }
+    }
+
+    pub fn chunks(&self) -> &[T] {
+        &self.chunks
+    }
+
+    pub fn into_chunks(self) -> Vec<T> {
+        self.chunks
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    // number of chunks needed for a number with this many decimal places, with 32 guard bits like the client
+    pub fn chunks_for_digits(digits: usize) -> usize {
+        let half_bits = T::HALF_BITS;
+        let bits = (digits as f64*std::f64::consts::LOG2_10).ceil() as usize + 32;
+        1 + bits.div_ceil(half_bits)
+    }
+
+    pub fn zero(chunks: usize) -> HpFixed<T> {
+        HpFixed::from_chunks(vec![T::zero(); chunks])
+    }
+
+    // truncate, or extend with zero chunks
+    pub fn with_chunks(&self, chunks: usize) -> HpFixed<T> {
+        let mut r = self.chunks.clone();
+        r.resize(chunks, T::zero());
+        HpFixed::from_chunks(r)
+    }
+
+    // convert a decimal string to this many chunks, truncating the magnitude like convert() in MB.html
+    pub fn from_decimal(s: &str, chunks: usize) -> Result<HpFixed<T>, ParseHpFixedError> {
+        assert!(chunks > 0);
+        let half_bits = T::HALF_BITS;
+        let Decimal { neg, int_digits, mut frac_digits } = parse_decimal(s)?;
+
+        let mut int_part = 0u128;
+        for d in int_digits {
+            int_part = int_part*10 + d as u128;
+            if int_part >= 1 << (half_bits - 1) {
+                return Err(ParseHpFixedError("integer part out of range"));
+            }
+        }
+
+        let mut x = vec![int_part as u64];
+        for _ in 1..chunks {
+            // shift the next half_bits bits of the fraction into the integer position
+            let mut carry = 0u128;
+            for d in frac_digits.iter_mut().rev() {
+                let v = ((*d as u128) << half_bits) + carry;
+                *d = (v % 10) as u8;
+                carry = v/10;
+            }
+            x.push(carry as u64);
+        }
+        if neg {
+            negate_u64(&mut x, half_bits);
+        }
+        Ok(HpFixed::from_chunks(x.iter().map(| &xi | T::from_u64(xi)).collect()))
+    }
+
+    // from the 16 bit chunks sent by the client
+    pub fn from_u32_chunks(a: &[u32]) -> HpFixed<T> {
+        HpFixed::from_chunks(u32_to_t(a))
+    }
+
+    // to 16 bit chunks as sent by the client; the integer part is cut to 16 bits
+    pub fn to_u32_chunks(&self) -> Vec<u32> {
+        let half_bits = T::HALF_BITS;
+        let mut r = vec![(AsPrimitive::<u64>::as_(self.chunks[0]) & 0xFFFF) as u32];
+        for c in &self.chunks[1..] {
+            let c: u64 = c.as_();
+            for k in 1..=half_bits/16 {
+                r.push(((c >> (half_bits - k*16)) & 0xFFFF) as u32);
+            }
+        }
+        r
+    }
+
+    // the integer chunk is signed and the fraction chunks are not, so the chunks can just be added up
+    pub fn to_double_double(&self) -> DoubleDouble {
+        let chunks = self.to_u32_chunks();
+        let mut r = DoubleDouble::from(chunks[0] as i16 as f64);
+        let mut scale = 1.0;
+        for &c in &chunks[1..] {
+            scale /= 65536.0;
+            r = r + DoubleDouble::from(c as f64*scale);
+        }
+        r
+    }
+
+    fn is_negative(&self) -> bool {
+        let half_bits = T::HALF_BITS;
+        (AsPrimitive::<u64>::as_(self.chunks[0]) >> (half_bits - 1)) & 1 != 0
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        t_to_f64(&self.chunks)
+    }
+
+    pub fn to_floatexp(&self) -> FloatExp {
+        t_to_floatexp(&self.chunks)
+    }
+}
+
+impl<T> FromStr for HpFixed<T>
+where T: Limb,
+{
+    type Err = ParseHpFixedError;
+
+    // use enough chunks for the decimal places given
+    fn from_str(s: &str) -> Result<HpFixed<T>, ParseHpFixedError> {
+        let digits = parse_decimal(s)?.frac_digits.len();
+        HpFixed::from_decimal(s, HpFixed::<T>::chunks_for_digits(digits))
+    }
+}
+
+impl FromStr for FloatExp {
+    type Err = ParseHpFixedError;
+
+    // only the first 17 significant digits matter to an f64 mantissa; unlike parsing an f64,
+    // this doesn't underflow at deep zooms
+    fn from_str(s: &str) -> Result<FloatExp, ParseHpFixedError> {
+        let decimal = parse_decimal(s)?;
+        let point = decimal.int_digits.len() as i64;
+        let digits: Vec<u8> = decimal.int_digits.into_iter().chain(decimal.frac_digits).collect();
+        let Some(first) = digits.iter().position(| &d | d != 0) else {
+            return Ok(FloatExp::zero());
+        };
+        let significant = &digits[first..digits.len().min(first + 17)];
+        let m = significant.iter().fold(0.0, | m, &d | 10.0*m + d as f64);
+        let x = FloatExp::from(m)*pow10(point - (first + significant.len()) as i64);
+        Ok(if decimal.neg { -x } else { x })
+    }
+}
+
+// 10^n by repeated squaring
+fn pow10(n: i64) -> FloatExp {
+    let (mut result, mut base) = (FloatExp::from(1.0), FloatExp::from(10.0));
+    let mut e = n.unsigned_abs();
+    while e > 0 {
+        if e & 1 == 1 {
+            result = result*base;
+        }
+        base = base.sq();
+        e >>= 1;
+    }
+    if n < 0 { FloatExp::from(1.0)/result } else { result }
+}
+
+impl<T> fmt::Display for HpFixed<T>
+where T: Limb,
+{
+    // with a precision, the magnitude is truncated to that many decimal places; otherwise just
+    // enough places are printed, rounding the magnitude up, for the string to parse back to the same chunks
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let half_bits = T::HALF_BITS;
+        let low_bits = u64::MAX >> (64 - half_bits);
+        let mut x: Vec<u64> = self.chunks.iter().map(| c | c.as_()).collect();
+        let neg = self.is_negative();
+        if neg {
+            negate_u64(&mut x, half_bits);
+        }
+        let mut int_part = x[0];
+        let frac = &mut x[1..];
+
+        // floor(log10(2^fraction bits)) + 1 places are enough to tell neighbouring values apart
+        let places = f.precision().unwrap_or(frac.len()*half_bits*301_030/1_000_000 + 1);
+        let mut digits = Vec::with_capacity(places);
+        for _ in 0..places {
+            let mut carry = 0u128;
+            for c in frac.iter_mut().rev() {
+                let v = *c as u128*10 + carry;
+                *c = v as u64 & low_bits;
+                carry = v >> half_bits;
+            }
+            digits.push(carry as u8);
+        }
+
+        if f.precision().is_none() {
+            if frac.iter().any(| &c | c != 0) {
+                let mut i = digits.len();
+                loop {
+                    if i == 0 {
+                        int_part += 1;
+                        break;
+                    }
+                    i -= 1;
+                    if digits[i] == 9 {
+                        digits[i] = 0;
+                    } else {
+                        digits[i] += 1;
+                        break;
+                    }
+                }
+            }
+            while digits.last() == Some(&0) {
+                digits.pop();
+            }
+        }
+
+        let mut s = String::with_capacity(digits.len() + 24);
+        if neg {
+            s.push('-');
+        }
+        s.push_str(&int_part.to_string());
+        if !digits.is_empty() {
+            s.push('.');
+            s.extend(digits.iter().map(| &d | (b'0' + d) as char));
+        }
+        f.write_str(&s)
+    }
+}
+
+impl<T> Ord for HpFixed<T>
+where T: Limb,
+{
+    fn cmp(&self, other: &HpFixed<T>) -> Ordering {
+        // with equal signs, two's complement numbers compare like unsigned ones
+        let neg = self.is_negative();
+        if neg != other.is_negative() {
+            return if neg { Ordering::Less } else { Ordering::Greater };
+        }
+        let zero = T::zero();
+        let n = core::cmp::max(self.len(), other.len());
+        for i in 0..n {
+            let a = self.chunks.get(i).unwrap_or(&zero);
+            let b = other.chunks.get(i).unwrap_or(&zero);
+            match a.cmp(b) {
+                Ordering::Equal => (),
+                ord => return ord,
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+impl<T> PartialOrd for HpFixed<T>
+where T: Limb,
+{
+    fn partial_cmp(&self, other: &HpFixed<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> PartialEq for HpFixed<T>
+where T: Limb,
+{
+    fn eq(&self, other: &HpFixed<T>) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for HpFixed<T>
+where T: Limb,
+{}
+
+impl<T> Add for &HpFixed<T>
+where T: Limb,
+{
+    type Output = HpFixed<T>;
+
+    fn add(self, rhs: &HpFixed<T>) -> HpFixed<T> {
+        let n = core::cmp::max(self.len(), rhs.len());
+        let mut out = vec![T::zero(); n];
+        add(&self.with_chunks(n).chunks, &rhs.with_chunks(n).chunks, &mut out);
+        HpFixed::from_chunks(out)
+    }
+}
+
+impl<T> Add for HpFixed<T>
+where T: Limb,
+{
+    type Output = HpFixed<T>;
+
+    fn add(self, rhs: HpFixed<T>) -> HpFixed<T> {
+        &self + &rhs
+    }
+}
+
+impl<T> Neg for &HpFixed<T>
+where T: Limb,
+{
+    type Output = HpFixed<T>;
+
+    fn neg(self) -> HpFixed<T> {
+        let mut out = vec![T::zero(); self.len()];
+        negate(&self.chunks, &mut out);
+        HpFixed::from_chunks(out)
+    }
+}
+
+impl<T> Neg for HpFixed<T>
+where T: Limb,
+{
+    type Output = HpFixed<T>;
+
+    fn neg(self) -> HpFixed<T> {
+        -&self
+    }
+}
+
+impl<T> Sub for &HpFixed<T>
+where T: Limb,
+{
+    type Output = HpFixed<T>;
+
+    fn sub(self, rhs: &HpFixed<T>) -> HpFixed<T> {
+        self + &-rhs
+    }
+}
+
+impl<T> Sub for HpFixed<T>
+where T: Limb,
+{
+    type Output = HpFixed<T>;
+
+    fn sub(self, rhs: HpFixed<T>) -> HpFixed<T> {
+        &self - &rhs
+    }
+}
+
+impl<T> Mul for &HpFixed<T>
+where T: Limb,
+{
+    type Output = HpFixed<T>;
+
+    fn mul(self, rhs: &HpFixed<T>) -> HpFixed<T> {
+        let n = core::cmp::max(self.len(), rhs.len());
+        let mut work1 = vec![T::zero(); n];
+        let mut work2 = vec![T::zero(); n];
+        let mut out = vec![T::zero(); n];
+        multiply(&self.with_chunks(n).chunks, &rhs.with_chunks(n).chunks, &mut work1, &mut work2, &mut out);
+        HpFixed::from_chunks(out)
+    }
+}
+
+impl<T> Mul for HpFixed<T>
+where T: Limb,
+{
+    type Output = HpFixed<T>;
+
+    fn mul(self, rhs: HpFixed<T>) -> HpFixed<T> {
+        &self * &rhs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use crate::{ Limb, FloatExp };
+    use super::{ HpFixed, ParseHpFixedError, MAX_DECIMAL_DIGITS };
+
+    const DECIMALS: [&str; 8] = [
+        "0",
+        "1.5",
+        "-1.5",
+        "0.1",
+        "-0.000123456789",
+        "-1.860460516298673172230333018784270044276096283022855952480965923931782897072038074843157",
+        "0.000127117449884649210392863748142587684982284304423743585496794271755259268163325756400",
+        "12.3456e-30",
+    ];
+
+    // relative difference of two FloatExps
+    fn relative_error(a: FloatExp, b: FloatExp) -> f64 {
+        ((a - b)/b).to_f64().abs()
+    }
+
+    fn display_round_trips<T>()
+    where T: Limb,
+    {
+        for s in DECIMALS {
+            let x: HpFixed<T> = s.parse().unwrap();
+            let printed = x.to_string();
+            assert_eq!(HpFixed::<T>::from_decimal(&printed, x.len()).unwrap(), x, "{} printed as {}", s, printed);
+        }
+    }
+
+    #[test]
+    fn display_round_trips_u32() {
+        display_round_trips::<u32>();
+    }
+
+    #[test]
+    fn display_round_trips_u64() {
+        display_round_trips::<u64>();
+    }
+
+    #[test]
+    fn display_round_trips_u128() {
+        display_round_trips::<u128>();
+    }
+
+    #[test]
+    fn exact_values_print_exactly() {
+        for (s, printed) in [("1.5", "1.5"), ("-1.5", "-1.5"), ("-.75", "-0.75"), ("+2", "2"), ("-0.0625", "-0.0625"), ("25e-2", "0.25")] {
+            assert_eq!(HpFixed::<u64>::from_str(s).unwrap().to_string(), printed);
+        }
+        let x = HpFixed::<u64>::from_decimal("-1.5", 3).unwrap();
+        assert_eq!(format!("{:.3}", x), "-1.500");
+    }
+
+    #[test]
+    fn negatives_negate() {
+        for s in DECIMALS {
+            let x = HpFixed::<u64>::from_decimal(s, 5).unwrap();
+            // the magnitude is truncated before it's negated
+            let neg_s = s.strip_prefix('-').map_or(format!("-{}", s), str::to_string);
+            let neg = HpFixed::<u64>::from_decimal(&neg_s, 5).unwrap();
+            assert_eq!(-&x, neg, "{}", s);
+            assert_eq!(x.to_f64(), -neg.to_f64());
+        }
+        assert_eq!(HpFixed::<u32>::from_str("-0.5").unwrap().to_f64(), -0.5);
+        assert_eq!(HpFixed::<u32>::from_str("-3").unwrap().to_f64(), -3.0);
+    }
+
+    #[test]
+    fn no_integer_part() {
+        for (short, long) in [(".5", "0.5"), ("-.5", "-0.5"), (".000123", "0.000123"), (".5e1", "5")] {
+            assert_eq!(HpFixed::<u64>::from_decimal(short, 4).unwrap(), HpFixed::<u64>::from_decimal(long, 4).unwrap());
+            assert_eq!(FloatExp::from_str(short).unwrap(), FloatExp::from_str(long).unwrap());
+        }
+        for s in [".", "-.", "", "e5"] {
+            assert_eq!(HpFixed::<u64>::from_decimal(s, 4), Err(ParseHpFixedError("no digits")), "{:?}", s);
+            assert!(FloatExp::from_str(s).is_err());
+        }
+    }
+
+    #[test]
+    fn too_many_digits() {
+        let digits = "1".repeat(MAX_DECIMAL_DIGITS);
+        assert!(HpFixed::<u64>::from_str(&format!(".{}", digits)).is_ok());
+        assert!(FloatExp::from_str(&format!(".{}", digits)).is_ok());
+        for s in [format!(".{}1", digits), format!("0.{}", digits), format!("-{}.1", digits)] {
+            assert_eq!(HpFixed::<u64>::from_str(&s), Err(ParseHpFixedError("too many digits")));
+            assert_eq!(FloatExp::from_str(&s), Err(ParseHpFixedError("too many digits")));
+        }
+        let s = format!("1e-{}", MAX_DECIMAL_DIGITS + 1);
+        assert_eq!(HpFixed::<u64>::from_str(&s), Err(ParseHpFixedError("exponent out of range")));
+        assert_eq!(FloatExp::from_str(&s), Err(ParseHpFixedError("exponent out of range")));
+    }
+
+    #[test]
+    fn floatexp_from_str() {
+        for (s, x) in [("1.5", 1.5), ("-2.5", -2.5), ("0.1", 0.1), ("-.000123456789", -0.000123456789), ("6.02214076e23", 6.02214076e23)] {
+            assert!(relative_error(FloatExp::from_str(s).unwrap(), FloatExp::from(x)) < 1.0e-15, "{}", s);
+        }
+        assert_eq!(FloatExp::from_str("-0.000").unwrap(), FloatExp::zero());
+
+        // below the smallest f64
+        let tiny = FloatExp::from_str("-1.5e-400").unwrap();
+        assert!(relative_error(tiny, -(FloatExp::from(1.5e-200)*FloatExp::from(1.0e-200))) < 1.0e-15);
+
+        // agrees with the high precision value
+        for s in DECIMALS.into_iter().filter(| &s | s != "0") {
+            assert!(relative_error(FloatExp::from_str(s).unwrap(), HpFixed::<u64>::from_str(s).unwrap().to_floatexp()) < 1.0e-15, "{}", s);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+/*
+    Interior data for points inside the Mandelbrot set
+    By Bill Wood
+
+    Besides the iteration count, these kernels return the final z of every point, and for points
+    whose orbit settles into an attracting cycle, the period of the cycle and the interior distance
+    estimate, so the inside of the set can be colored too.  The cycle is found the same way as in
+    periodicity checking, by an exact repeat of a saved orbit value, so these kernels always check
+    periodicity; points that reach max_iterations before their orbit repeats have period 0.
+    They also don't skip the main cardioid and period 2 bulb, since the final z is needed.
+    For points that escape, they return the exterior distance estimate of distance.rs as well, so a
+    request for both doesn't have to iterate the point twice.
+
+    Rounding can make the exact cycle a few times longer than the real one, wandering between
+    values that differ in the last few bits, so the period is the first divisor p of the exact
+    cycle's length for which z comes back to within a tolerance of where it started.
+
+    Over one period p, starting from a point z0 of the cycle, the derivatives of z with respect to
+    z0 and c are iterated along with z:
+        dz/dz0' = 2*z*dz/dz0                        dz/dc' = 2*z*dz/dc + 1
+        d2z/dz0dz0' = 2*(dz/dz0^2 + z*d2z/dz0dz0)     d2z/dcdz0' = 2*(dz/dz0*dz/dc + z*d2z/dcdz0)
+    Then with w = dz/dz0, the multiplier of the cycle, the distance to the boundary is about
+        (1 - |w|^2)/|d2z/dcdz0 + d2z/dz0dz0*dz/dc/(1 - w)|
+    which is computed multiplying through by |1 - w| to avoid complex division.  The derivatives with
+    respect to c grow about as fast as the zoom, so the high precision kernel keeps them in FloatExp.
+*/
+
+use core::cmp::PartialEq;
+use crate::{ Limb, HPData, DoubleDouble, FloatExp, Delta, Formula, Mandelbrot, sq, add, negate, t_to_f64, bailout_chunks, escaped_hp,
+    periodicity_save, pixel_distance };
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Interior {
+    // the final value of z, for all points
+    pub zx: f64,
+    pub zy: f64,
+    // period of the attracting cycle, or 0 if the point escaped or its cycle wasn't found
+    pub period: i32,
+    // distance to the boundary of the set in pixels, or 0 if the period is 0
+    pub distance: f64,
+}
+
+// the period of the attracting cycle, given the length of the exact cycle, which is a multiple of it;
+// step moves z one step around the cycle and returns |z - z0|^2
+fn cycle_period(exact_period: i32, tolerance: f64, mut step: impl FnMut() -> f64) -> i32 {
+    for p in 1..exact_period {
+        let dist = step();
+        if exact_period % p == 0 && dist < tolerance*tolerance {
+            return p;
+        }
+    }
+    exact_period
+}
+
+// complex multiplication
+#[inline]
+fn cmul<D: Delta>(a: (D, D), b: (D, D)) -> (D, D) {
+    (a.0*b.0 - a.1*b.1, a.0*b.1 + a.1*b.0)
+}
+
+// the derivatives of z over one period of the cycle
+struct CycleDerivatives<D: Delta> {
+    dz_dz0: (D, D),
+    dz_dc: (D, D),
+    d2z_dz0dz0: (D, D),
+    d2z_dcdz0: (D, D),
+}
+
+impl<D: Delta> CycleDerivatives<D> {
+    fn new() -> CycleDerivatives<D> {
+        let (zero, one) = (D::from_f64(0.0), D::from_f64(1.0));
+        CycleDerivatives { dz_dz0: (one, zero), dz_dc: (zero, zero), d2z_dz0dz0: (zero, zero), d2z_dcdz0: (zero, zero) }
+    }
+
+    // update the derivatives for one step from z
+    #[inline]
+    fn step(&mut self, zx: f64, zy: f64) {
+        let (one, two) = (D::from_f64(1.0), D::from_f64(2.0));
+        let z = (D::from_f64(zx), D::from_f64(zy));
+        let twice = | a: (D, D) | (two*a.0, two*a.1);
+        let plus = | a: (D, D), b: (D, D) | (a.0 + b.0, a.1 + b.1);
+
+        self.d2z_dcdz0 = twice(plus(cmul(self.dz_dz0, self.dz_dc), cmul(z, self.d2z_dcdz0)));
+        self.d2z_dz0dz0 = twice(plus(cmul(self.dz_dz0, self.dz_dz0), cmul(z, self.d2z_dz0dz0)));
+        let dz_dc = twice(cmul(z, self.dz_dc));
+        self.dz_dc = (dz_dc.0 + one, dz_dc.1);
+        self.dz_dz0 = twice(cmul(z, self.dz_dz0));
+    }
+
+    // the interior distance in pixels, or 0 if the cycle isn't attracting
+    fn distance(&self, dx: D) -> f64 {
+        let w = (self.dz_dz0.0.to_f64(), self.dz_dz0.1.to_f64());
+        let w_norm = w.0*w.0 + w.1*w.1;
+        if w_norm >= 1.0 {
+            return 0.0;
+        }
+        let a = (D::from_f64(1.0 - w.0), D::from_f64(-w.1));
+        let (b1, b2) = (cmul(self.d2z_dcdz0, a), cmul(self.d2z_dz0dz0, self.dz_dc));
+        let b = (b1.0 + b2.0, b1.1 + b2.1);
+        let a_norm = (1.0 - w.0)*(1.0 - w.0) + w.1*w.1;
+        (1.0 - w_norm)*a_norm.sqrt()/((b.0*b.0 + b.1*b.1)*dx*dx).to_f64().sqrt()
+    }
+}
+
+// *** low precision *** //
+// points of a cycle closer than this are taken to be the same point; about half the bits of an f64
+const F64_CYCLE_TOLERANCE: f64 = 1e-8;
+
+// returns the iteration count, the final value of zx*zx + zy*zy for smooth_iteration_count, the exterior distance in
+// pixels as in count_iterations_distance, and the interior data
+pub fn count_iterations_interior(x: f64, y: f64, dx: f64, max_iterations: i32, bailout: f64) -> (i32, f64, f64, Interior) {
+    let mut count = 0;
+    let mut zx = x;
+    let mut zy = y;
+    let (mut dzx, mut dzy) = (1.0, 0.0);
+    let mut norm = zx*zx + zy*zy;
+    let (mut check_zx, mut check_zy, mut check_count) = (zx, zy, 0);
+
+    while count < max_iterations && norm < bailout {
+        // dz = 2*z*dz + 1
+        (dzx, dzy) = (2.0*(zx*dzx - zy*dzy) + 1.0, 2.0*(zx*dzy + zy*dzx));
+        (zx, zy) = Mandelbrot.step(zx, zy, x, y);
+        count += 1;
+        norm = zx*zx + zy*zy;
+
+        if zx == check_zx && zy == check_zy {
+            // z is on the cycle
+            let period = cycle_period(count - check_count, F64_CYCLE_TOLERANCE, || {
+                (zx, zy) = Mandelbrot.step(zx, zy, x, y);
+                (zx - check_zx)*(zx - check_zx) + (zy - check_zy)*(zy - check_zy)
+            });
+            (zx, zy) = (check_zx, check_zy);
+            let mut derivatives = CycleDerivatives::<f64>::new();
+            for _ in 0..period {
+                derivatives.step(zx, zy);
+                (zx, zy) = Mandelbrot.step(zx, zy, x, y);
+            }
+            return (-1, 0.0, 0.0, Interior { zx, zy, period, distance: derivatives.distance(dx) });
+        }
+        if periodicity_save(count) {
+            (check_zx, check_zy, check_count) = (zx, zy, count);
+        }
+    }
+
+    let interior = Interior { zx, zy, ..Interior::default() };
+    if count < max_iterations {
+        (count, norm, pixel_distance(norm, (dzx*dzx + dzy*dzy)*dx*dx), interior)
+    } else {
+        (-1, 0.0, 0.0, interior)
+    }
+}
+
+
+// *** double-double precision *** //
+const DD_CYCLE_TOLERANCE: f64 = 1e-16;
+
+// same as count_iterations_interior
+pub fn count_iterations_dd_interior(x: DoubleDouble, y: DoubleDouble, dx: f64, max_iterations: i32, bailout: f64) -> (i32, f64, f64, Interior) {
+    let mut count = 0;
+    let mut zx = x;
+    let mut zy = y;
+    let (mut dzx, mut dzy) = (1.0, 0.0);
+
+    // the low parts are too small to matter for the bailout test
+    let mut norm = zx.hi()*zx.hi() + zy.hi()*zy.hi();
+    let (mut check_zx, mut check_zy, mut check_count) = (zx, zy, 0);
+    while count < max_iterations && norm < bailout {
+        (dzx, dzy) = (2.0*(zx.hi()*dzx - zy.hi()*dzy) + 1.0, 2.0*(zx.hi()*dzy + zy.hi()*dzx));
+        (zx, zy) = Mandelbrot.step_dd(zx, zy, x, y);
+        count += 1;
+        norm = zx.hi()*zx.hi() + zy.hi()*zy.hi();
+
+        if zx == check_zx && zy == check_zy {
+            let period = cycle_period(count - check_count, DD_CYCLE_TOLERANCE, || {
+                (zx, zy) = Mandelbrot.step_dd(zx, zy, x, y);
+                let (dzx, dzy) = ((zx - check_zx).hi(), (zy - check_zy).hi());
+                dzx*dzx + dzy*dzy
+            });
+            (zx, zy) = (check_zx, check_zy);
+            let mut derivatives = CycleDerivatives::<f64>::new();
+            for _ in 0..period {
+                derivatives.step(zx.hi(), zy.hi());
+                (zx, zy) = Mandelbrot.step_dd(zx, zy, x, y);
+            }
+            return (-1, 0.0, 0.0, Interior { zx: zx.hi(), zy: zy.hi(), period, distance: derivatives.distance(dx) });
+        }
+        if periodicity_save(count) {
+            (check_zx, check_zy, check_count) = (zx, zy, count);
+        }
+    }
+
+    let interior = Interior { zx: zx.hi(), zy: zy.hi(), ..Interior::default() };
+    if count < max_iterations {
+        (count, norm, pixel_distance(norm, (dzx*dzx + dzy*dzy)*dx*dx), interior)
+    } else {
+        (-1, 0.0, 0.0, interior)
+    }
+}
+
+
+// *** high precision *** //
+// same loop as count_iterations_hp; dx is the pixel spacing, eg from t_to_floatexp
+pub fn count_iterations_hp_interior<T>(hp_data: &mut HPData<T>, x: &[T], y: &[T], dx: FloatExp, max_iterations: i32, bailout: f64) -> (i32, f64, f64, Interior)
+where T: Limb,
+{
+    let mut count = 0;
+    hp_data.zx.copy_from_slice(x);
+    hp_data.zy.copy_from_slice(y);
+    let (one, two) = (FloatExp::from(1.0), FloatExp::from(2.0));
+    let (mut dzx, mut dzy) = (one, FloatExp::zero());
+    hp_data.check_zx.copy_from_slice(x);
+    hp_data.check_zy.copy_from_slice(y);
+    let mut check_count = 0;
+
+    let bailout = bailout_chunks(bailout);
+
+    // while count < max_iterations && zx*zx + zy*zy < bailout {
+    while count < max_iterations {
+        sq(&hp_data.zx, &mut hp_data.work3, &mut hp_data.work1);
+        sq(&hp_data.zy, &mut hp_data.work3, &mut hp_data.work2);
+        add(&hp_data.work1, &hp_data.work2, &mut hp_data.work3);
+        if escaped_hp(&hp_data.work3, bailout) {
+            let norm = t_to_f64(&hp_data.work3);
+            let interior = Interior { zx: t_to_f64(&hp_data.zx), zy: t_to_f64(&hp_data.zy), ..Interior::default() };
+            return (count, norm, pixel_distance(norm, ((dzx*dzx + dzy*dzy)*dx*dx).to_f64()), interior);
+        }
+
+        // dz = 2*z*dz + 1, as in count_iterations_hp_distance
+        let zx = FloatExp::from(t_to_f64(&hp_data.zx));
+        let zy = FloatExp::from(t_to_f64(&hp_data.zy));
+        (dzx, dzy) = (two*(zx*dzx - zy*dzy) + one, two*(zx*dzy + zy*dzx));
+
+        Mandelbrot.step_hp(hp_data, x, y);
+        count += 1;
+
+        if hp_data.zx == hp_data.check_zx && hp_data.zy == hp_data.check_zy {
+            // go around the cycle in high precision, since at deep zooms c doesn't fit in an f64;
+            // the tolerance is half the bits of the fraction
+            let fraction_bits = (x.len() - 1)*T::HALF_BITS;
+            let tolerance = (0.5f64).powi(fraction_bits as i32/2);
+            let period = cycle_period(count - check_count, tolerance, || {
+                sq(&hp_data.zx, &mut hp_data.work3, &mut hp_data.work1);
+                sq(&hp_data.zy, &mut hp_data.work3, &mut hp_data.work2);
+                Mandelbrot.step_hp(hp_data, x, y);
+                negate(&hp_data.check_zx, &mut hp_data.work3);
+                add(&hp_data.zx, &hp_data.work3, &mut hp_data.work4);
+                let dzx = t_to_f64(&hp_data.work4);
+                negate(&hp_data.check_zy, &mut hp_data.work3);
+                add(&hp_data.zy, &hp_data.work3, &mut hp_data.work4);
+                let dzy = t_to_f64(&hp_data.work4);
+                dzx*dzx + dzy*dzy
+            });
+            hp_data.zx.copy_from_slice(&hp_data.check_zx);
+            hp_data.zy.copy_from_slice(&hp_data.check_zy);
+            let mut derivatives = CycleDerivatives::<FloatExp>::new();
+            for _ in 0..period {
+                derivatives.step(t_to_f64(&hp_data.zx), t_to_f64(&hp_data.zy));
+                sq(&hp_data.zx, &mut hp_data.work3, &mut hp_data.work1);
+                sq(&hp_data.zy, &mut hp_data.work3, &mut hp_data.work2);
+                Mandelbrot.step_hp(hp_data, x, y);
+            }
+            let interior = Interior { zx: t_to_f64(&hp_data.zx), zy: t_to_f64(&hp_data.zy), period, distance: derivatives.distance(dx) };
+            return (-1, 0.0, 0.0, interior);
+        }
+        if periodicity_save(count) {
+            hp_data.check_zx.copy_from_slice(&hp_data.zx);
+            hp_data.check_zy.copy_from_slice(&hp_data.zy);
+            check_count = count;
+        }
+    }
+    (-1, 0.0, 0.0, Interior { zx: t_to_f64(&hp_data.zx), zy: t_to_f64(&hp_data.zy), ..Interior::default() })
+}
@@ -0,0 +1,56 @@
+/*
+    Julia sets
+    By Bill Wood
+
+    Same iteration as the Mandelbrot set, z = z*z + c, except that c is fixed for the whole image
+    and z starts at the pixel instead of at c.  Points of the Mandelbrot set give connected Julia
+    sets, so c is usually picked from the Mandelbrot view.  There is no cardioid or bulb to skip.
+*/
+
+use crate::{ Limb, HPData, DoubleDouble, Mandelbrot, t_to_f64, iterate_formula_smooth, iterate_dd_formula_smooth, iterate_hp_formula };
+
+// *** low precision *** //
+pub fn count_iterations_julia(x: f64, y: f64, cx: f64, cy: f64, max_iterations: i32, bailout: f64, periodicity: bool) -> i32 {
+    count_iterations_julia_smooth(x, y, cx, cy, max_iterations, bailout, periodicity).0
+}
+
+// also returns the final value of zx*zx + zy*zy, for smooth_iteration_count
+#[inline]
+pub fn count_iterations_julia_smooth(x: f64, y: f64, cx: f64, cy: f64, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64) {
+    iterate_formula_smooth(&Mandelbrot, x, y, cx, cy, max_iterations, bailout, periodicity)
+}
+
+
+
+// *** double-double precision *** //
+pub fn count_iterations_dd_julia(x: DoubleDouble, y: DoubleDouble, cx: DoubleDouble, cy: DoubleDouble, max_iterations: i32, bailout: f64, periodicity: bool) -> i32 {
+    count_iterations_dd_julia_smooth(x, y, cx, cy, max_iterations, bailout, periodicity).0
+}
+
+// also returns the final value of zx*zx + zy*zy, for smooth_iteration_count
+#[inline]
+pub fn count_iterations_dd_julia_smooth(x: DoubleDouble, y: DoubleDouble, cx: DoubleDouble, cy: DoubleDouble, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64) {
+    iterate_dd_formula_smooth(&Mandelbrot, x, y, cx, cy, max_iterations, bailout, periodicity)
+}
+
+
+// *** high precision *** //
+// the Mandelbrot driver, adding cx and cy instead of x and y
+pub fn count_iterations_hp_julia<T>(hp_data: &mut HPData<T>, x: &[T], y: &[T], cx: &[T], cy: &[T], max_iterations: i32, bailout: f64) -> i32
+where T: Limb,
+{
+    iterate_hp_formula(&Mandelbrot, hp_data, x, y, cx, cy, max_iterations, bailout)
+}
+
+// also returns the final value of zx*zx + zy*zy, for smooth_iteration_count
+pub fn count_iterations_hp_julia_smooth<T>(hp_data: &mut HPData<T>, x: &[T], y: &[T], cx: &[T], cy: &[T], max_iterations: i32, bailout: f64) -> (i32, f64)
+where T: Limb,
+{
+    // count_iterations_hp_julia leaves zx*zx + zy*zy in work3 when a point escapes
+    let count = count_iterations_hp_julia(hp_data, x, y, cx, cy, max_iterations, bailout);
+    if count < 0 {
+        (count, 0.0)
+    } else {
+        (count, t_to_f64(&hp_data.work3))
+    }
+}
@@ -0,0 +1,327 @@
+/*
+    Karatsuba multiplication for long high precision numbers
+    By Bill Wood
+
+    multiply_pos doesn't compute the full product: it sums all partial products x[j]*y[i] with
+    j + i < count, plus the high halves of those with j + i == count, and keeps the result
+    mod 2^(count*T::HALF_BITS).  Every step of that sum is exact, so any other way of computing
+    the same sum gives bit-identical results.  Here the triangle of partial products is split as
+    in Mulders' "short product": a square of the most significant halves, which is a full product
+    done with Karatsuba, plus two triangles half the size, done recursively.
+
+    Unlike the high precision numbers, the digit arrays here are little endian, and every digit
+    is less than 2^T::HALF_BITS.
+*/
+
+use crate::Limb;
+
+// multiply_pos switches to Karatsuba at this many chunks; below that the schoolbook
+// multiply_pos is faster, since the short product has to be exact to stay bit-identical.
+// Measured per multiply, schoolbook vs Karatsuba splitting down to MAX_SPLIT chunks:
+//     chunks   u32            u64            u128
+//     96       1.04x faster   1.00x          0.90x
+//     128      1.10x          1.15x          0.93x
+//     192      1.31x          1.29x          1.05x
+//     256      1.38x          1.49x          1.07x
+// so 128 is where u32 and u64 clearly win, at a small cost for u128 up to about 192 chunks.
+// A 100 digit view is only about 6 to 12 chunks, far below where Karatsuba can help; it pays
+// off for views of thousands of digits
+pub const KARATSUBA_THRESHOLD: usize = 128;
+
+// sq_pos switches to Karatsuba at this many times the threshold, since the schoolbook sq_pos only
+// computes half the products; Karatsuba squaring only wins for u32 and u64 from about 768 chunks
+const SQ_THRESHOLD_SCALE: usize = 6;
+
+// subproblems smaller than this are done schoolbook
+const MAX_SPLIT: usize = 48;
+
+// Karatsuba needs at least this many digits to split into smaller subproblems
+const MIN_SPLIT: usize = 4;
+
+// the number of chunks at which sq_pos switches to Karatsuba, for a multiply_pos threshold
+pub(crate) fn karatsuba_sq_threshold(threshold: usize) -> usize {
+    threshold.saturating_mul(SQ_THRESHOLD_SCALE)
+}
+
+// the Karatsuba subproblem size below which the schoolbook is used, for a multiply_pos threshold
+fn split_threshold(threshold: usize) -> usize {
+    threshold.clamp(MIN_SPLIT, MAX_SPLIT)
+}
+
+// acc += a*2^(shift*T::HALF_BITS), discarding anything past the end of acc
+fn add_shifted<T>(acc: &mut [T], a: &[T], shift: usize)
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let mut carry = T::zero();
+    let mut i = shift;
+    for &d in a {
+        if i >= acc.len() {
+            return;
+        }
+        let mut s = acc[i];
+        s += d;
+        s += carry;
+        carry = s >> t_half_bits;
+        s &= t_low_bits;
+        acc[i] = s;
+        i += 1;
+    }
+    while carry != T::zero() && i < acc.len() {
+        let mut s = acc[i];
+        s += carry;
+        carry = s >> t_half_bits;
+        s &= t_low_bits;
+        acc[i] = s;
+        i += 1;
+    }
+}
+
+// acc -= a, where acc >= a
+fn sub_in_place<T>(acc: &mut [T], a: &[T])
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let t_base = t_low_bits + T::one();
+    let mut borrow = T::zero();
+    for i in 0..acc.len() {
+        if i >= a.len() && borrow == T::zero() {
+            break;
+        }
+        let ai = if i < a.len() { a[i] } else { T::zero() };
+        let mut d = acc[i];
+        d += t_base;
+        let mut d = d - ai - borrow;
+        borrow = T::one() - (d >> t_half_bits);
+        d &= t_low_bits;
+        acc[i] = d;
+    }
+}
+
+// out = a*b, where out is zeroed and out.len() == a.len() + b.len()
+fn mul_school<T>(a: &[T], b: &[T], out: &mut [T])
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    debug_assert!(out.len() == a.len() + b.len());
+    for (i, &ai) in a.iter().enumerate() {
+        let mut carry = T::zero();
+        for (j, &bj) in b.iter().enumerate() {
+            // out[i + j] += ai*bj + carry;
+            let t = unsafe { out.get_unchecked_mut(i + j) };
+            *t += ai*bj + carry;
+            carry = *t >> t_half_bits;
+            *t &= t_low_bits;
+        }
+        // out[i + b.len()] = carry;
+        unsafe { *out.get_unchecked_mut(i + b.len()) = carry };
+    }
+}
+
+// out = a*b, where a.len() == b.len(), out is zeroed and out.len() == 2*a.len()
+fn karatsuba<T>(a: &[T], b: &[T], out: &mut [T], split: usize)
+where T: Limb,
+{
+    let n = a.len();
+    if n < split {
+        mul_school(a, b, out);
+        return;
+    }
+
+    // a = a0 + a1*B^m, b = b0 + b1*B^m
+    let m = n/2;
+    let k = n - m;
+    let (a0, a1) = a.split_at(m);
+    let (b0, b1) = b.split_at(m);
+
+    // z0 = a0*b0 and z2 = a1*b1 go straight into the low and high parts of out
+    karatsuba(a0, b0, &mut out[..2*m], split);
+    karatsuba(a1, b1, &mut out[2*m..], split);
+
+    // z1 = (a0 + a1)*(b0 + b1) - z0 - z2
+    let mut sum_a = a1.to_vec();
+    sum_a.push(T::zero());
+    add_shifted(&mut sum_a, a0, 0);
+    let mut sum_b = b1.to_vec();
+    sum_b.push(T::zero());
+    add_shifted(&mut sum_b, b0, 0);
+    let mut z1 = vec![T::zero(); 2*(k + 1)];
+    karatsuba(&sum_a, &sum_b, &mut z1, split);
+    sub_in_place(&mut z1, &out[..2*m]);
+    sub_in_place(&mut z1, &out[2*m..]);
+
+    add_shifted(out, &z1, m);
+}
+
+// acc += the sum of x[j]*y[i]*B^(k - 1 - j - i) over j + i < k, where x and y are big endian with length k
+fn short_product<T>(x: &[T], y: &[T], acc: &mut [T], split: usize)
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let k = x.len();
+    if k < split {
+        for (j, &xj) in x.iter().enumerate() {
+            // x[j]*y[i] lands on digit k - 1 - j - i
+            debug_assert!(acc.len() >= k);
+            let mut carry = T::zero();
+            for (e, &yi) in y[0..k - j].iter().rev().enumerate() {
+                // acc[e] += xj*yi + carry;
+                let t = unsafe { acc.get_unchecked_mut(e) };
+                *t += xj*yi + carry;
+                carry = *t >> t_half_bits;
+                *t &= t_low_bits;
+            }
+            let mut e = k - j;
+            while carry != T::zero() && e < acc.len() {
+                // acc[e] += carry;
+                let t = unsafe { acc.get_unchecked_mut(e) };
+                *t += carry;
+                carry = *t >> t_half_bits;
+                *t &= t_low_bits;
+                e += 1;
+            }
+        }
+        return;
+    }
+
+    // the square of partial products from the l most significant chunks lies entirely inside the triangle
+    let l = k - k/2;
+    let h = k/2;
+    let xl: Vec<T> = x[0..l].iter().rev().copied().collect();
+    let yl: Vec<T> = y[0..l].iter().rev().copied().collect();
+    let mut product = vec![T::zero(); 2*l];
+    karatsuba(&xl, &yl, &mut product, split);
+    add_shifted(acc, &product, k + 1 - 2*l);
+
+    // the rest of the triangle is two triangles of size h
+    short_product(&x[0..h], &y[l..k], acc, split);
+    short_product(&x[l..k], &y[0..h], acc, split);
+}
+
+// same result as the schoolbook multiply_pos, bit for bit; threshold is the one multiply_pos switched at
+pub(crate) fn multiply_pos_karatsuba<T>(x: &[T], y: &[T], out: &mut [T], threshold: usize)
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let count = out.len();
+    let mut acc = vec![T::zero(); count];
+    short_product(&x[0..count], &y[0..count], &mut acc, split_threshold(threshold));
+
+    // multiply_pos also adds in the high halves of the partial products just past the end of out
+    let mut high = T::zero();
+    for j in 1..count {
+        high += (x[j]*y[count - j]) >> t_half_bits;
+    }
+    let mut high_low = high;
+    high_low &= t_low_bits;
+    add_shifted(&mut acc, &[high_low, high >> t_half_bits], 0);
+
+    for (o, &a) in out.iter_mut().zip(acc.iter().rev()) {
+        *o = a;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ Limb, multiply_threshold, sq_threshold };
+    use super::karatsuba_sq_threshold;
+
+    // xorshift, so the operands are the same on every run
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        // a high precision number of random chunks, negative about half the time
+        fn hp<T>(&mut self, chunks: usize) -> Vec<T>
+        where T: Limb,
+        {
+            (0..chunks).map(| _ | {
+                let mut r = T::zero();
+                for _ in 0..T::HALF_BITS/16 {
+                    r = r << 16;
+                    r |= T::from(self.next() as u32 & 0xFFFF);
+                }
+                r
+            }).collect()
+        }
+    }
+
+    // odd chunk counts leave the Karatsuba halves and the short product triangles unequal
+    const CHUNKS: [usize; 8] = [5, 7, 9, 13, 31, 49, 67, 101];
+
+    fn karatsuba_matches_schoolbook<T>()
+    where T: Limb,
+    {
+        let mut rng = Rng(0x9E3779B97F4A7C15);
+        for chunks in CHUNKS {
+            for _ in 0..20 {
+                let x = rng.hp::<T>(chunks);
+                let y = rng.hp::<T>(chunks);
+                let (mut work1, mut work2) = (vec![T::zero(); chunks], vec![T::zero(); chunks]);
+                let mut schoolbook = vec![T::zero(); chunks];
+                multiply_threshold(&x, &y, &mut work1, &mut work2, &mut schoolbook, usize::MAX);
+                let mut karatsuba = vec![T::zero(); chunks];
+                multiply_threshold(&x, &y, &mut work1, &mut work2, &mut karatsuba, 4);
+                assert_eq!(schoolbook, karatsuba, "{chunks} chunks of {} bits, x = {x:x?}, y = {y:x?}", T::SIZE_BITS);
+            }
+        }
+    }
+
+    #[test]
+    fn karatsuba_matches_schoolbook_u32() {
+        karatsuba_matches_schoolbook::<u32>();
+    }
+
+    #[test]
+    fn karatsuba_matches_schoolbook_u64() {
+        karatsuba_matches_schoolbook::<u64>();
+    }
+
+    #[test]
+    fn karatsuba_matches_schoolbook_u128() {
+        karatsuba_matches_schoolbook::<u128>();
+    }
+
+    // with a threshold of 4, squaring switches to Karatsuba at 4*SQ_THRESHOLD_SCALE chunks; CHUNKS covers
+    // the schoolbook sq_pos below that and Karatsuba above it
+    fn sq_matches_multiply<T>()
+    where T: Limb,
+    {
+        assert!(CHUNKS.iter().any(| &chunks | chunks < karatsuba_sq_threshold(4)) && CHUNKS.iter().any(| &chunks | chunks >= karatsuba_sq_threshold(4)));
+        let mut rng = Rng(0xD1B54A32D192ED03);
+        for chunks in CHUNKS {
+            for threshold in [4, usize::MAX] {
+                for _ in 0..20 {
+                    let x = rng.hp::<T>(chunks);
+                    let (mut work1, mut work2) = (vec![T::zero(); chunks], vec![T::zero(); chunks]);
+                    let mut product = vec![T::zero(); chunks];
+                    multiply_threshold(&x, &x, &mut work1, &mut work2, &mut product, usize::MAX);
+                    let mut square = vec![T::zero(); chunks];
+                    sq_threshold(&x, &mut work1, &mut square, threshold);
+                    assert_eq!(product, square, "{chunks} chunks of {} bits, threshold {threshold}, x = {x:x?}", T::SIZE_BITS);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sq_matches_multiply_u32() {
+        sq_matches_multiply::<u32>();
+    }
+
+    #[test]
+    fn sq_matches_multiply_u64() {
+        sq_matches_multiply::<u64>();
+    }
+
+    #[test]
+    fn sq_matches_multiply_u128() {
+        sq_matches_multiply::<u128>();
+    }
+}
@@ -5,72 +5,163 @@
 */
 
 
+// *** periodicity checking *** //
+// a point caught in a cycle never escapes, so once its orbit exactly repeats a saved value it can stop
+// with -1 instead of running to max_iterations.  Values are saved at iteration counts that are powers
+// of 2 (Brent's method), so any cycle is found within a few periods once the orbit has converged to it.
+// Since only exact repeats are detected, the iteration counts are the same with or without checking.
+// The f64, double-double and fixed size HP kernels take it as an argument, the others from their HPData
+
+// true at the iteration counts where the orbit is saved for periodicity checking
+#[inline]
+fn periodicity_save(count: i32) -> bool {
+    count & (count - 1) == 0
+}
+
+
+// *** bailout *** //
+// points escape once zx*zx + zy*zy >= bailout, the square of the bailout radius
+pub const DEFAULT_BAILOUT: f64 = 8.0;
+
+// smaller bailouts would let points in the set escape
+pub const MIN_BAILOUT: f64 = 4.0;
+
+// the largest Multibrot power: each iteration takes power - 1 complex multiplies, and above this even
+// u128 chunks don't have room for MIN_BAILOUT (see max_bailout_hp)
+pub const MAX_POWER: u32 = 31;
+
+
 // *** low precision *** //
-pub fn count_iterations(x: f64, y: f64, max_iterations: i32) -> i32 {
-    let mut count = 0;
-    let mut zx = x;
-    let mut zy = y;
+pub fn count_iterations(x: f64, y: f64, max_iterations: i32, bailout: f64, periodicity: bool) -> i32 {
+    count_iterations_smooth(x, y, max_iterations, bailout, periodicity).0
+}
 
-    while count < max_iterations && zx*zx + zy*zy < 8.0 {
-        let new_zx = zx*zx - zy*zy + x;
-        zy = 2.0*zx*zy + y;
-        zx = new_zx;
-        count += 1;
+// also returns the final value of zx*zx + zy*zy, for smooth_iteration_count
+#[inline]
+pub fn count_iterations_smooth(x: f64, y: f64, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64) {
+    if in_main_cardioid_or_bulb(x, y) {
+        return (-1, 0.0);
     }
+    count_iterations_formula_smooth(&Mandelbrot, x, y, max_iterations, bailout, periodicity)
+}
 
-    if count < max_iterations {
-        count
+// the main cardioid and the period 2 bulb are inside the set, so points in them don't need iterating:
+//     q*(q + (x - 1/4)) <= y*y/4, where q = (x - 1/4)^2 + y*y
+//     (x + 1)^2 + y*y < 1/16
+#[inline]
+pub fn in_main_cardioid_or_bulb(x: f64, y: f64) -> bool {
+    let y2 = y*y;
+    let xq = x - 0.25;
+    let q = xq*xq + y2;
+    q*(q + xq) <= 0.25*y2 || (x + 1.0)*(x + 1.0) + y2 < 0.0625
+}
+
+// fractional iteration count from the count and final zx*zx + zy*zy of an escaped point, for coloring without
+// banding; it is count + 1 when the final value is just past the bailout, and falls smoothly to count as the
+// final value approaches the bailout to the power of power.  -1 for points in the set
+pub fn smooth_iteration_count(count: i32, norm: f64, bailout: f64, power: u32) -> f64 {
+    if count < 0 {
+        -1.0
     } else {
-        -1
+        count as f64 + 1.0 - (norm.ln()/bailout.ln()).ln()/(power as f64).ln()
     }
 }
 
 
+// *** double-double precision *** //
+mod double_double;
+pub use double_double::*;
+
+
 // *** high precision *** //
-use std::ops::{ BitAnd, BitXor, BitAndAssign, BitOrAssign, Shl, Shr, AddAssign, Sub, Mul };
+use std::ops::{ Add, BitAnd, BitXor, BitAndAssign, BitOrAssign, Shl, Shr, AddAssign, Sub, Mul };
+use core::fmt;
 use num::traits::{ Zero, One, AsPrimitive };
-use core::cmp::PartialEq;
 use core::mem::size_of;
 
-macro_rules! t_bit_info {
-    () => {
-        {
-            let t_size_bits = size_of::<T>()*8;
-            let t_low_bits = u64::MAX >> (64 - t_size_bits/2);
-            (t_size_bits, t_low_bits.as_())
-        }
+// the unsigned integers high precision numbers are made of; each limb holds HALF_BITS bits, leaving the
+// upper half for the carries of add and multiply
+pub trait Limb: Zero + One + Copy + Send + Sync + 'static + Ord + fmt::Debug + fmt::LowerHex +
+    Add<Output = Self> + AddAssign + Sub<Output = Self> + Mul<Output = Self> +
+    BitAnd<Output = Self> + BitAndAssign + BitOrAssign + BitXor<Output = Self> +
+    Shl<usize, Output = Self> + Shr<usize, Output = Self> +
+    From<u32> + AsPrimitive<f64> + AsPrimitive<u64>
+{
+    const SIZE_BITS: usize;
+    const HALF_BITS: usize;
+    // the low HALF_BITS bits set
+    const LOW_BITS: Self;
+
+    // keeps the low SIZE_BITS bits
+    fn from_u64(x: u64) -> Self;
+}
+
+macro_rules! impl_limb {
+    ($($t:ty),*) => {
+        $(
+            impl Limb for $t {
+                const SIZE_BITS: usize = size_of::<$t>()*8;
+                const HALF_BITS: usize = size_of::<$t>()*4;
+                const LOW_BITS: $t = <$t>::MAX >> (size_of::<$t>()*4);
+
+                #[inline]
+                fn from_u64(x: u64) -> $t {
+                    x as $t
+                }
+            }
+        )*
     };
 }
 
+impl_limb!(u32, u64, u128);
+
 pub struct HPData<T> {
     work1: Vec<T>,
     work2: Vec<T>,
     work3: Vec<T>,
     work4: Vec<T>,
+    // for Multibrot powers of z
+    work5: Vec<T>,
+    work6: Vec<T>,
     zx: Vec<T>,
     zy: Vec<T>,
+    // saved orbit value for periodicity checking
+    check_zx: Vec<T>,
+    check_zy: Vec<T>,
+    periodicity: bool,
 }
 
 impl<T> HPData<T> {
+    // with periodicity checking
     pub fn new(chunks: usize) -> HPData::<T>
-    where T: Zero + Copy,
+    where T: Limb,
+    {
+        HPData::with_periodicity(chunks, true)
+    }
+
+    pub fn with_periodicity(chunks: usize, periodicity: bool) -> HPData::<T>
+    where T: Limb,
     {
         HPData::<T> {
             work1: vec![T::zero(); chunks],
             work2: vec![T::zero(); chunks],
             work3: vec![T::zero(); chunks],
             work4: vec![T::zero(); chunks],
+            work5: vec![T::zero(); chunks],
+            work6: vec![T::zero(); chunks],
             zx: vec![T::zero(); chunks],
             zy: vec![T::zero(); chunks],
+            check_zx: vec![T::zero(); chunks],
+            check_zy: vec![T::zero(); chunks],
+            periodicity,
         }
     }
 }
 
 pub fn u32_to_t<T>(a: &[u32]) -> Vec<T>
-where T: BitOrAssign + BitXor<Output = T> + Shl<usize, Output = T> + From<u32> + Copy + 'static,
-    u64: AsPrimitive<T>
+where T: Limb,
 {
-    let (t_size_bits, t_low_bits) = t_bit_info!();
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
     let mut r = vec![];
 
     r.push(a[0].into());
@@ -82,18 +173,44 @@ where T: BitOrAssign + BitXor<Output = T> + Shl<usize, Output = T> + From<u32> +
     let mut i = 1;
     while i < a.len() {
         let mut k = 1;
-        r.push(T::from(a[i]) << (t_size_bits/2 - k*16));
+        r.push(T::from(a[i]) << (t_half_bits - k*16));
         i += 1;
         let rlast = r.len() - 1;
-        while k < t_size_bits/32 && i < a.len() {
+        while k < t_half_bits/16 && i < a.len() {
             k += 1;
-            r[rlast] |= T::from(a[i]) << (t_size_bits/2 - k*16);
+            r[rlast] |= T::from(a[i]) << (t_half_bits - k*16);
             i += 1;
         }
     }
     r
 }
 
+// convert a high precision number to the nearest f64, eg for use as a perturbation reference orbit
+pub fn t_to_f64<T>(x: &[T]) -> f64
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let t_neg_test = (t_low_bits + T::one()) >> 1;
+    let neg = x[0] & t_neg_test != T::zero();
+
+    // for a negative number, take the two's complement on the fly: complement every chunk
+    // above the lowest non-zero one, which instead becomes (t_low_bits + 1) - x[i]
+    let last = if neg { x.iter().rposition(| &xi | xi != T::zero()).unwrap_or(0) } else { x.len() - 1 };
+    let scale = (2.0f64).powi(-(t_half_bits as i32));
+    let mut r = 0.0;
+    for i in (0..=last).rev() {
+        let chunk: f64 = if !neg {
+            AsPrimitive::<f64>::as_(x[i])
+        } else if i == last {
+            AsPrimitive::<f64>::as_(t_low_bits - x[i]) + 1.0
+        } else {
+            AsPrimitive::<f64>::as_(t_low_bits - x[i])
+        };
+        r = r*scale + chunk;
+    }
+    if neg { -r } else { r }
+}
+
 /*
 function countIterationsHP( /* Uint32Array */ x, /* Uint32Array */ y, maxIterations) {
     arraycopy(x,0,zx,0,chunks);
@@ -128,48 +245,110 @@ function arraycopy( sourceArray, sourceStart, destArray, destStart, count ) {
    }
 }
 */
-pub fn count_iterations_hp<T>(hp_data: &mut HPData<T>, x: &[T], y: &[T], max_iterations: i32) -> i32
-where T: Zero + BitAnd + Shr<usize, Output = T> + Shl<usize, Output = T> + Copy + 'static,
-    <T as BitAnd>::Output: PartialEq<T>,
-    u64: AsPrimitive<T>,
-    T: std::fmt::LowerHex,
-    // add, sq, multiply, negate requirements
-    T: One + AddAssign + BitAndAssign + Sub<Output = T> + PartialEq,
+pub fn count_iterations_hp<T>(hp_data: &mut HPData<T>, x: &[T], y: &[T], max_iterations: i32, bailout: f64) -> i32
+where T: Limb,
 {
-    let mut count = 0;
-    hp_data.zx.copy_from_slice(x);
-    hp_data.zy.copy_from_slice(y);
-
-    let (_, t_low_bits) = t_bit_info!();
-    let t_8_test = (t_low_bits >> 3) << 3;
-    // it's called the "what test" because I haven't figured out what it does :)
-    let t_8_what_test = (t_low_bits >> 4) << 4;
-
-    // while count < max_iterations && zx*zx + zy*zy < 8.0 {
-    while count < max_iterations {
-        sq(&hp_data.zx, &mut hp_data.work3, &mut hp_data.work1);
-        sq(&hp_data.zy, &mut hp_data.work3, &mut hp_data.work2);
-        add(&hp_data.work1, &hp_data.work2, &mut hp_data.work3);
-        // let test8 = hp_data.work3[0] & t_8_test;
-        let test8 = unsafe { *hp_data.work3.get_unchecked(0) } & t_8_test;
-        if test8 != T::zero() && test8 != t_8_what_test {
-            return count;
-        }
+    if in_main_cardioid_or_bulb_hp(hp_data, x, y) {
+        return -1;
+    }
+    count_iterations_hp_formula(&Mandelbrot, hp_data, x, y, max_iterations, bailout)
+}
 
-        add(&hp_data.zx, &hp_data.zx, &mut hp_data.work4);
+// also returns the final value of zx*zx + zy*zy, for smooth_iteration_count
+pub fn count_iterations_hp_smooth<T>(hp_data: &mut HPData<T>, x: &[T], y: &[T], max_iterations: i32, bailout: f64) -> (i32, f64)
+where T: Limb,
+{
+    // count_iterations_hp leaves zx*zx + zy*zy in work3 when a point escapes
+    let count = count_iterations_hp(hp_data, x, y, max_iterations, bailout);
+    if count < 0 {
+        (count, 0.0)
+    } else {
+        (count, t_to_f64(&hp_data.work3))
+    }
+}
 
-        // zx = zx*zx - zy*zy + x;
-        negate(&hp_data.work2, &mut hp_data.work3);
-        add(&hp_data.work1, &hp_data.work3, &mut hp_data.work2);
-        add(&hp_data.work2, x, &mut hp_data.zx);
+// the largest bailout that can be used with T chunks: while iterating, zx*zx + zy*zy can get up to
+// about (bailout^(power/2) + |c|)^2, which has to fit in the integer part of a chunk, less the sign bit
+pub fn max_bailout_hp<T>(power: u32) -> f64
+where T: Limb,
+{
+    let half_bits = T::HALF_BITS;
+    ((2.0f64).powi(half_bits as i32 - 1).sqrt() - 4.0).powf(2.0/power as f64)
+}
+
+// the bailout as the integer part and first fraction chunk of a high precision number
+pub(crate) fn bailout_chunks<T>(bailout: f64) -> (T, T)
+where T: Limb,
+{
+    let half_bits = T::HALF_BITS;
+    let int_part = bailout.floor();
+    let fraction = (bailout - int_part)*(2.0f64).powi(half_bits as i32);
+    (T::from_u64(int_part as u64), T::from_u64(fraction as u64))
+}
+
+// zx*zx + zy*zy >= bailout, given zx*zx + zy*zy in norm; a norm of just the integer chunk has a zero fraction
+#[inline]
+pub(crate) fn escaped_hp<T>(norm: &[T], bailout: (T, T)) -> bool
+where T: Limb,
+{
+    // norm[0] > bailout.0 || (norm[0] == bailout.0 && norm[1] >= bailout.1)
+    let norm0 = unsafe { *norm.get_unchecked(0) };
+    if norm0 != bailout.0 {
+        return norm0 > bailout.0;
+    }
+    match norm.get(1) {
+        Some(&norm1) => norm1 >= bailout.1,
+        None => bailout.1 == T::zero(),
+    }
+}
+
+// same tests as in_main_cardioid_or_bulb, using the work areas of hp_data
+pub(crate) fn in_main_cardioid_or_bulb_hp<T>(hp_data: &mut HPData<T>, x: &[T], y: &[T]) -> bool
+where T: Limb,
+{
+    // the tests need a fraction chunk; with just the integer part, the points are iterated
+    if x.len() < 2 {
+        return false;
+    }
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let t_neg_test = (t_low_bits + T::one()) >> 1;
 
-        // zy = 2.0*zx*zy + y;
-        multiply(&hp_data.work4, &hp_data.zy, &mut hp_data.work1, &mut hp_data.work3, &mut hp_data.work2);
-        add(&hp_data.work2, y, &mut hp_data.zy);
+    // work2 = y*y
+    sq(y, &mut hp_data.work3, &mut hp_data.work2);
 
-        count += 1;
+    // period 2 bulb: work4 = x + 1, work3 = (x + 1)^2 + y*y, which is < 1/16 if its top 4 fraction bits are 0
+    hp_data.work4.copy_from_slice(x);
+    hp_data.work4[0] += T::one();
+    hp_data.work4[0] &= t_low_bits;
+    sq(&hp_data.work4, &mut hp_data.work3, &mut hp_data.work1);
+    add(&hp_data.work1, &hp_data.work2, &mut hp_data.work3);
+    if hp_data.work3[0] == T::zero() && hp_data.work3[1] >> (t_half_bits - 4) == T::zero() {
+        return true;
     }
-    -1
+
+    // main cardioid: work4 = x - 1/4, computed as x - 1 + 3/4
+    hp_data.work4.copy_from_slice(x);
+    let mut s = hp_data.work4[1];
+    s += t_low_bits - (t_low_bits >> 2);
+    let carry = s >> t_half_bits;
+    s &= t_low_bits;
+    hp_data.work4[1] = s;
+    hp_data.work4[0] += carry;
+    hp_data.work4[0] += t_low_bits;
+    hp_data.work4[0] &= t_low_bits;
+
+    sq(&hp_data.work4, &mut hp_data.work3, &mut hp_data.work1);
+    // work3 = q
+    add(&hp_data.work1, &hp_data.work2, &mut hp_data.work3);
+    add(&hp_data.work3, &hp_data.work4, &mut hp_data.work1);
+    // zy = q*(q + (x - 1/4)), zx = 4*zy
+    multiply(&hp_data.work3, &hp_data.work1, &mut hp_data.work4, &mut hp_data.zx, &mut hp_data.zy);
+    add(&hp_data.zy, &hp_data.zy, &mut hp_data.work1);
+    add(&hp_data.work1, &hp_data.work1, &mut hp_data.zx);
+    // inside if y*y - zx >= 0
+    negate(&hp_data.zx, &mut hp_data.work1);
+    add(&hp_data.work2, &hp_data.work1, &mut hp_data.work3);
+    hp_data.work3[0] & t_neg_test == T::zero()
 }
 
 /*
@@ -185,11 +364,9 @@ function negate( /* int[] */ x, /* int */ chunks) {
 }
 */
 pub fn negate<T>(x: &[T], out: &mut[T])
-where T: Zero + One + AddAssign + BitAnd + BitAndAssign + Sub<Output = T> + Copy + 'static,
-    <T as BitAnd>::Output: PartialEq<T>,
-    u64: AsPrimitive<T>
+where T: Limb,
 {
-    let (_, t_low_bits) = t_bit_info!();
+    let t_low_bits = T::LOW_BITS;
     let chunks = out.len();
     for i in 0..chunks {
         // out[i] = t_low_bits - x[i];
@@ -213,6 +390,20 @@ where T: Zero + One + AddAssign + BitAnd + BitAndAssign + Sub<Output = T> + Copy
     unsafe { *out.get_unchecked_mut(0) &= t_low_bits };
 }
 
+// out = |x|
+pub fn abs<T>(x: &[T], out: &mut[T])
+where T: Limb,
+{
+    let t_low_bits = T::LOW_BITS;
+    let t_neg_test = (t_low_bits + T::one()) >> 1;
+    // if x[0] & t_neg_test != T::zero() {
+    if unsafe { *x.get_unchecked(0) } & t_neg_test != T::zero() {
+        negate(x, out);
+    } else {
+        out.copy_from_slice(x);
+    }
+}
+
 /*
 function add( /* int[] */ x, /* int[] */ dx, /* int */ count) {
     let carry = 0;
@@ -225,36 +416,34 @@ function add( /* int[] */ x, /* int[] */ dx, /* int */ count) {
 }
 */
 pub fn incr<T>(x: &mut [T], dx: &[T])
-where T: Zero + AddAssign + Shr<usize, Output = T> + BitAndAssign + Copy + 'static,
-    u64: AsPrimitive<T>
+where T: Limb,
 {
-    let (t_size_bits, t_low_bits) = t_bit_info!();
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
     let mut carry = T::zero();
     let mut i = x.len();
     while i > 0 {
         i -= 1;
         // x[i] += dx[i] + carry;
         unsafe { *x.get_unchecked_mut(i) += *dx.get_unchecked(i) + carry };
-        // carry = x[i] >> t_size_bits/2;
-        carry = unsafe { *x.get_unchecked(i) } >> t_size_bits/2;
+        // carry = x[i] >> t_half_bits;
+        carry = unsafe { *x.get_unchecked(i) } >> t_half_bits;
         // x[i] &= t_low_bits;
         unsafe { *x.get_unchecked_mut(i) &= t_low_bits };
     }
 }
 
 pub fn add<T>(x: &[T], y: &[T], out: &mut[T])
-where T: Zero + AddAssign + Shr<usize, Output = T> + BitAndAssign + Copy + 'static,
-    u64: AsPrimitive<T>
+where T: Limb,
 {
-    let (t_size_bits, t_low_bits) = t_bit_info!();
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
     let mut carry = T::zero();
     let mut i = out.len();
     while i > 0 {
         i -= 1;
         // out[i] = x[i] + y[i] + carry;
         unsafe { *out.get_unchecked_mut(i) = *x.get_unchecked(i) + *y.get_unchecked(i) + carry };
-        // carry = out[i] >> t_size_bits/2;
-        carry = unsafe { *out.get_unchecked(i) } >> t_size_bits/2;
+        // carry = out[i] >> t_half_bits;
+        carry = unsafe { *out.get_unchecked(i) } >> t_half_bits;
         // out[i] &= t_low_bits;
         unsafe { *out.get_unchecked_mut(i) &= t_low_bits };
     }
@@ -307,13 +496,16 @@ function multiply( /* int[] */ x, /* int[] */ y, /* int */ count){  // Can't all
 }
 */
 pub fn multiply<T>(x: &[T], y: &[T], work1: &mut [T], work2: &mut [T], out: &mut [T])
-where T: Zero + One + BitAnd + Shr<usize, Output = T> + Copy + 'static,
-    <T as BitAnd>::Output: PartialEq<T>,
-    u64: AsPrimitive<T>,
-    // negate and multiply_pos requirements
-    T: AddAssign + BitAndAssign + Sub<Output = T> + PartialEq,
+where T: Limb,
 {
-    let (_, t_low_bits) = t_bit_info!();
+    multiply_threshold(x, y, work1, work2, out, KARATSUBA_THRESHOLD);
+}
+
+// multiply, switching to Karatsuba at threshold chunks
+pub(crate) fn multiply_threshold<T>(x: &[T], y: &[T], work1: &mut [T], work2: &mut [T], out: &mut [T], threshold: usize)
+where T: Limb,
+{
+    let t_low_bits = T::LOW_BITS;
     let t_neg_test = (t_low_bits + T::one()) >> 1;
 
     // let negx = (x[0] & t_neg_test) != T::zero();
@@ -323,27 +515,30 @@ where T: Zero + One + BitAnd + Shr<usize, Output = T> + Copy + 'static,
     if negx != negy {
         if negx {
             negate(x, work1);
-            multiply_pos(work1, y, work2);
+            multiply_pos(work1, y, work2, threshold);
         } else {
             negate(y, work1);
-            multiply_pos(x, work1, work2);
+            multiply_pos(x, work1, work2, threshold);
         }
         negate(work2, out);
     } else if negx && negy {
         negate(x, work1);
         negate(y, work2);
-        multiply_pos(work1, work2, out);
+        multiply_pos(work1, work2, out, threshold);
     } else {
-        multiply_pos(x, y, out);
+        multiply_pos(x, y, out, threshold);
     }
 }
 
-fn multiply_pos<T>(x: &[T], y: &[T], out: &mut [T])
-where T: Zero + AddAssign + Mul<Output = T> + Shr<usize, Output = T> + BitAndAssign + PartialEq + Copy + 'static,
-    u64: AsPrimitive<T>
+fn multiply_pos<T>(x: &[T], y: &[T], out: &mut [T], threshold: usize)
+where T: Limb,
 {
-    let (t_size_bits, t_low_bits) = t_bit_info!();
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
     let count = out.len();
+    if count >= threshold {
+        multiply_pos_karatsuba(x, y, out, threshold);
+        return;
+    }
 
     // let x0 = x[0];
     let x0 = unsafe { *x.get_unchecked(0) };
@@ -359,8 +554,8 @@ where T: Zero + AddAssign + Mul<Output = T> + Shr<usize, Output = T> + BitAndAss
             i -= 1;
             // out[i] = x0*y[i] + carry;
             unsafe { *out.get_unchecked_mut(i) = x0* *y.get_unchecked(i) + carry };
-            // carry = out[i] >> t_size_bits/2;
-            carry = unsafe { *out.get_unchecked(i) } >> t_size_bits/2;
+            // carry = out[i] >> t_half_bits;
+            carry = unsafe { *out.get_unchecked(i) } >> t_half_bits;
             // out[i] &= t_low_bits;
             unsafe { *out.get_unchecked_mut(i) &= t_low_bits };
         }
@@ -368,15 +563,15 @@ where T: Zero + AddAssign + Mul<Output = T> + Shr<usize, Output = T> + BitAndAss
 
     for j in 1..count {
         let mut i = count - j;
-        // let mut carry = (x[j]*y[i]) >> t_size_bits/2;
-        let mut carry = unsafe { *x.get_unchecked(j)* *y.get_unchecked(i) } >> t_size_bits/2;
+        // let mut carry = (x[j]*y[i]) >> t_half_bits;
+        let mut carry = unsafe { *x.get_unchecked(j)* *y.get_unchecked(i) } >> t_half_bits;
         let mut k = count - 1;
         while i > 0 {
             i -= 1;
             // out[k] += x[j]*y[i] + carry;
             unsafe { *out.get_unchecked_mut(k) += *x.get_unchecked(j)* *y.get_unchecked(i) + carry };
-            // carry = out[k] >> t_size_bits/2;
-            carry = unsafe { *out.get_unchecked(k) } >> t_size_bits/2;
+            // carry = out[k] >> t_half_bits;
+            carry = unsafe { *out.get_unchecked(k) } >> t_half_bits;
             // out[k] &= t_low_bits;
             unsafe { *out.get_unchecked_mut(k) &= t_low_bits };
             k -= 1;
@@ -384,8 +579,8 @@ where T: Zero + AddAssign + Mul<Output = T> + Shr<usize, Output = T> + BitAndAss
         while carry != T::zero() {
             // out[k] += carry;
             unsafe { *out.get_unchecked_mut(k) += carry };
-            // carry = out[k] >> t_size_bits/2;
-            carry = unsafe { *out.get_unchecked(k) } >> t_size_bits/2;
+            // carry = out[k] >> t_half_bits;
+            carry = unsafe { *out.get_unchecked(k) } >> t_half_bits;
             // out[k] &= t_low_bits;
             unsafe { *out.get_unchecked_mut(k) &= t_low_bits };
             if k == 0 {
@@ -397,20 +592,249 @@ where T: Zero + AddAssign + Mul<Output = T> + Shr<usize, Output = T> + BitAndAss
 }
 
 pub fn sq<T>(x: &[T], work: &mut [T], out: &mut [T])
-where T: Zero + One + BitAnd + Shr<usize, Output = T> + Copy + 'static,
-    <T as BitAnd>::Output: PartialEq<T>,
-    u64: AsPrimitive<T>,
-    // negate and multiply_pos requirements
-    T: AddAssign + BitAndAssign + Sub<Output = T> + PartialEq,
+where T: Limb,
 {
-    let (_, t_low_bits) = t_bit_info!();
+    sq_threshold(x, work, out, KARATSUBA_THRESHOLD);
+}
+
+// sq, switching to Karatsuba at karatsuba_sq_threshold(threshold) chunks
+pub(crate) fn sq_threshold<T>(x: &[T], work: &mut [T], out: &mut [T], threshold: usize)
+where T: Limb,
+{
+    let t_low_bits = T::LOW_BITS;
     let t_neg_test = (t_low_bits + T::one()) >> 1;
     // let neg = (x[0] & t_neg_test) != T::zero();
     let neg = (unsafe { *x.get_unchecked(0) } & t_neg_test) != T::zero();
     if neg {
         negate(x, work);
-        multiply_pos(work, work, out);
+        sq_pos(work, out, threshold);
     } else {
-        multiply_pos(x, x, out);
+        sq_pos(x, out, threshold);
+    }
+}
+
+// same result as multiply_pos(x, x, out), bit for bit, but each cross product x[i]*x[j] is
+// computed once and doubled; the diagonal products x[i]*x[i] are added in afterwards
+fn sq_pos<T>(x: &[T], out: &mut [T], threshold: usize)
+where T: Limb,
+{
+    let (t_half_bits, t_low_bits) = (T::HALF_BITS, T::LOW_BITS);
+    let count = out.len();
+    if count >= karatsuba_sq_threshold(threshold) {
+        multiply_pos_karatsuba(x, x, out, threshold);
+        return;
+    }
+    out.fill(T::zero());
+
+    // cross products x[j]*x[i], i > j, including the high halves of those just past the end of out
+    for j in 0..count {
+        let mut i = count - j;
+        if i <= j {
+            break;
+        }
+        // let mut carry = (x[j]*x[i]) >> t_half_bits;
+        let mut carry = if j > 0 { (unsafe { *x.get_unchecked(j)* *x.get_unchecked(i) }) >> t_half_bits } else { T::zero() };
+        let mut k = count - 1;
+        while i > j + 1 {
+            i -= 1;
+            // out[k] += x[j]*x[i] + carry;
+            unsafe { *out.get_unchecked_mut(k) += *x.get_unchecked(j)* *x.get_unchecked(i) + carry };
+            // carry = out[k] >> t_half_bits;
+            carry = unsafe { *out.get_unchecked(k) } >> t_half_bits;
+            // out[k] &= t_low_bits;
+            unsafe { *out.get_unchecked_mut(k) &= t_low_bits };
+            k -= 1;
+        }
+        while carry != T::zero() {
+            // out[k] += carry;
+            unsafe { *out.get_unchecked_mut(k) += carry };
+            // carry = out[k] >> t_half_bits;
+            carry = unsafe { *out.get_unchecked(k) } >> t_half_bits;
+            // out[k] &= t_low_bits;
+            unsafe { *out.get_unchecked_mut(k) &= t_low_bits };
+            if k == 0 {
+                break;
+            }
+            k -= 1;
+        }
+    }
+
+    // double the cross products and add the diagonal, from the least significant chunk up;
+    // for even count the high half of x[count/2]*x[count/2] is also just past the end of out
+    let mut carry = if count.is_multiple_of(2) {
+        // (x[count/2]*x[count/2]) >> t_half_bits
+        (unsafe { *x.get_unchecked(count/2)* *x.get_unchecked(count/2) }) >> t_half_bits
+    } else {
+        T::zero()
+    };
+    let mut k = count;
+    while k > 0 {
+        k -= 1;
+        // let mut s = 2*out[k] + carry;
+        let out_k = unsafe { *out.get_unchecked(k) };
+        let mut s = out_k + out_k + carry;
+        if k.is_multiple_of(2) {
+            // let p = x[k/2]*x[k/2];
+            let p = unsafe { *x.get_unchecked(k/2)* *x.get_unchecked(k/2) };
+            let mut p_low = p;
+            p_low &= t_low_bits;
+            s += p_low;
+            carry = (s >> t_half_bits) + (p >> t_half_bits);
+        } else {
+            carry = s >> t_half_bits;
+        }
+        s &= t_low_bits;
+        // out[k] = s;
+        unsafe { *out.get_unchecked_mut(k) = s };
+    }
+}
+
+
+// *** perturbation *** //
+mod perturbation;
+pub use perturbation::*;
+
+
+// *** extended exponent floats *** //
+mod floatexp;
+pub use floatexp::*;
+
+
+// *** Karatsuba multiplication *** //
+mod karatsuba;
+pub use karatsuba::KARATSUBA_THRESHOLD;
+use karatsuba::{ multiply_pos_karatsuba, karatsuba_sq_threshold };
+
+
+// *** typed high precision numbers *** //
+mod hp_fixed;
+pub use hp_fixed::*;
+
+
+// *** Multibrot *** //
+mod multibrot;
+pub use multibrot::*;
+
+
+// *** Julia sets *** //
+mod julia;
+pub use julia::*;
+
+
+// *** fractal formulas *** //
+mod formula;
+pub use formula::*;
+
+
+// *** distance estimation *** //
+mod distance;
+pub use distance::*;
+
+
+// *** interior data *** //
+mod interior;
+pub use interior::*;
+
+
+// *** orbit traps *** //
+mod orbit_trap;
+pub use orbit_trap::*;
+
+
+// *** multi-pixel f64 kernel *** //
+mod simd;
+pub use simd::*;
+
+
+// *** automatic precision selection *** //
+mod precision;
+pub use precision::*;
+
+
+// *** fixed size high precision numbers *** //
+mod hp_array;
+pub use hp_array::*;
+
+
+// *** full width high precision numbers *** //
+mod full_width;
+pub use full_width::*;
+
+
+// *** batched high precision kernel *** //
+mod hp_batch;
+pub use hp_batch::*;
+
+
+// *** series approximation *** //
+mod series;
+pub use series::*;
+
+
+// *** bilinear approximation *** //
+mod bla;
+pub use bla::*;
+
+
+// *** nucleus finding *** //
+mod nucleus;
+pub use nucleus::*;
+
+
+// *** points for the kernel tests *** //
+#[cfg(test)]
+mod test_grid;
+
+
+#[cfg(test)]
+mod tests {
+    use super::{ Limb, HPData, FloatExp, DEFAULT_BAILOUT, floatexp_to_t, escaped_hp, bailout_chunks, count_iterations_smooth, count_iterations_hp,
+        count_iterations_hp_smooth };
+
+    #[test]
+    fn escaped_hp_one_chunk() {
+        let (four, four_and_a_half) = (bailout_chunks::<u64>(4.0), bailout_chunks::<u64>(4.5));
+        assert!(escaped_hp(&[5u64], four));
+        assert!(escaped_hp(&[4u64], four));
+        assert!(!escaped_hp(&[3u64], four));
+        assert!(escaped_hp(&[5u64], four_and_a_half));
+        assert!(!escaped_hp(&[4u64], four_and_a_half));
+
+        // the same as with a zero fraction chunk
+        for norm in [3u64, 4, 5] {
+            for bailout in [four, four_and_a_half] {
+                assert_eq!(escaped_hp(&[norm], bailout), escaped_hp(&[norm, 0], bailout));
+            }
+        }
+    }
+
+    // with one chunk the points are integers, so f64 gives the same results
+    fn one_chunk_matches_f64<T>()
+    where T: Limb,
+    {
+        let mut hp_data = HPData::<T>::new(1);
+        for x in -2..=1 {
+            for y in -1..=1 {
+                let (hp_x, hp_y) = (floatexp_to_t(FloatExp::from(x as f64), 1), floatexp_to_t(FloatExp::from(y as f64), 1));
+                let expected = count_iterations_smooth(x as f64, y as f64, 100, DEFAULT_BAILOUT, true);
+                assert_eq!(count_iterations_hp_smooth(&mut hp_data, &hp_x, &hp_y, 100, DEFAULT_BAILOUT), expected, "{x}, {y}");
+                assert_eq!(count_iterations_hp(&mut hp_data, &hp_x, &hp_y, 100, DEFAULT_BAILOUT), expected.0, "{x}, {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn one_chunk_matches_f64_u32() {
+        one_chunk_matches_f64::<u32>();
+    }
+
+    #[test]
+    fn one_chunk_matches_f64_u64() {
+        one_chunk_matches_f64::<u64>();
+    }
+
+    #[test]
+    fn one_chunk_matches_f64_u128() {
+        one_chunk_matches_f64::<u128>();
     }
 }
@@ -0,0 +1,117 @@
+/*
+    Multibrot sets
+    By Bill Wood
+
+    Iterates z = z^power + c for integer powers >= 2.  z^power is computed as z*z, then multiplied
+    by z power - 2 more times.  The iterating is done by the Formula drivers, with Multibrot as the
+    formula.  Power 2 goes to the regular Mandelbrot functions, which are faster and also skip the
+    main cardioid and period 2 bulb.
+*/
+
+use crate::{ Limb, HPData, DoubleDouble, Formula, add, negate, multiply, t_to_f64, count_iterations_smooth, count_iterations_dd_smooth,
+    count_iterations_hp, count_iterations_formula_smooth, count_iterations_dd_formula_smooth, count_iterations_hp_formula };
+
+// z = z^power + c
+pub struct Multibrot(pub u32);
+
+impl Formula for Multibrot {
+    #[inline]
+    fn step(&self, zx: f64, zy: f64, x: f64, y: f64) -> (f64, f64) {
+        let (mut px, mut py) = (zx*zx - zy*zy, 2.0*zx*zy);
+        for _ in 2..self.0 {
+            (px, py) = (px*zx - py*zy, px*zy + py*zx);
+        }
+        (px + x, py + y)
+    }
+
+    #[inline]
+    fn step_dd(&self, zx: DoubleDouble, zy: DoubleDouble, x: DoubleDouble, y: DoubleDouble) -> (DoubleDouble, DoubleDouble) {
+        let (mut px, mut py) = (zx.sq() - zy.sq(), (zx*zy).mul2());
+        for _ in 2..self.0 {
+            (px, py) = (px*zx - py*zy, px*zy + py*zx);
+        }
+        (px + x, py + y)
+    }
+
+    #[inline]
+    fn step_hp<T>(&self, hp_data: &mut HPData<T>, x: &[T], y: &[T])
+    where T: Limb,
+    {
+        // (px, py) = z*z = (zx*zx - zy*zy, 2.0*zx*zy), in (work5, work6)
+        negate(&hp_data.work2, &mut hp_data.work3);
+        add(&hp_data.work1, &hp_data.work3, &mut hp_data.work5);
+        add(&hp_data.zx, &hp_data.zx, &mut hp_data.work4);
+        multiply(&hp_data.work4, &hp_data.zy, &mut hp_data.work1, &mut hp_data.work3, &mut hp_data.work6);
+
+        for _ in 2..self.0 {
+            // (px, py) = (px*zx - py*zy, px*zy + py*zx)
+            multiply(&hp_data.work5, &hp_data.zx, &mut hp_data.work1, &mut hp_data.work2, &mut hp_data.work3);
+            multiply(&hp_data.work6, &hp_data.zy, &mut hp_data.work1, &mut hp_data.work2, &mut hp_data.work4);
+            negate(&hp_data.work4, &mut hp_data.work2);
+            add(&hp_data.work3, &hp_data.work2, &mut hp_data.work4);
+            multiply(&hp_data.work6, &hp_data.zx, &mut hp_data.work1, &mut hp_data.work2, &mut hp_data.work3);
+            multiply(&hp_data.work5, &hp_data.zy, &mut hp_data.work1, &mut hp_data.work2, &mut hp_data.work6);
+            add(&hp_data.work6, &hp_data.work3, &mut hp_data.work1);
+            std::mem::swap(&mut hp_data.work4, &mut hp_data.work5);
+            std::mem::swap(&mut hp_data.work1, &mut hp_data.work6);
+        }
+
+        add(&hp_data.work5, x, &mut hp_data.zx);
+        add(&hp_data.work6, y, &mut hp_data.zy);
+    }
+}
+
+
+// *** low precision *** //
+pub fn count_iterations_multibrot(x: f64, y: f64, max_iterations: i32, bailout: f64, power: u32, periodicity: bool) -> i32 {
+    count_iterations_multibrot_smooth(x, y, max_iterations, bailout, power, periodicity).0
+}
+
+// also returns the final value of zx*zx + zy*zy, for smooth_iteration_count
+#[inline]
+pub fn count_iterations_multibrot_smooth(x: f64, y: f64, max_iterations: i32, bailout: f64, power: u32, periodicity: bool) -> (i32, f64) {
+    if power == 2 {
+        return count_iterations_smooth(x, y, max_iterations, bailout, periodicity);
+    }
+    count_iterations_formula_smooth(&Multibrot(power), x, y, max_iterations, bailout, periodicity)
+}
+
+
+// *** double-double precision *** //
+pub fn count_iterations_dd_multibrot(x: DoubleDouble, y: DoubleDouble, max_iterations: i32, bailout: f64, power: u32, periodicity: bool) -> i32 {
+    count_iterations_dd_multibrot_smooth(x, y, max_iterations, bailout, power, periodicity).0
+}
+
+// also returns the final value of zx*zx + zy*zy, for smooth_iteration_count
+#[inline]
+pub fn count_iterations_dd_multibrot_smooth(x: DoubleDouble, y: DoubleDouble, max_iterations: i32, bailout: f64, power: u32, periodicity: bool) -> (i32, f64) {
+    if power == 2 {
+        return count_iterations_dd_smooth(x, y, max_iterations, bailout, periodicity);
+    }
+    count_iterations_dd_formula_smooth(&Multibrot(power), x, y, max_iterations, bailout, periodicity)
+}
+
+
+// *** high precision *** //
+// the bailout has to be at most max_bailout_hp::<T>(power)
+pub fn count_iterations_hp_multibrot<T>(hp_data: &mut HPData<T>, x: &[T], y: &[T], max_iterations: i32, bailout: f64, power: u32) -> i32
+where T: Limb,
+{
+    if power == 2 {
+        return count_iterations_hp(hp_data, x, y, max_iterations, bailout);
+    }
+    count_iterations_hp_formula(&Multibrot(power), hp_data, x, y, max_iterations, bailout)
+}
+
+// also returns the final value of zx*zx + zy*zy, for smooth_iteration_count
+pub fn count_iterations_hp_multibrot_smooth<T>(hp_data: &mut HPData<T>, x: &[T], y: &[T], max_iterations: i32, bailout: f64, power: u32) -> (i32, f64)
+where T: Limb,
+{
+    // both kernels leave zx*zx + zy*zy in work3 when a point escapes
+    let count = count_iterations_hp_multibrot(hp_data, x, y, max_iterations, bailout, power);
+    if count < 0 {
+        (count, 0.0)
+    } else {
+        (count, t_to_f64(&hp_data.work3))
+    }
+}
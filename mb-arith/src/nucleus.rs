@@ -0,0 +1,193 @@
+/*
+    Finding minibrots
+    By Bill Wood
+
+    Every minibrot sits on an atom, a hyperbolic component whose nucleus is the c where 0 is
+    periodic: z_p = 0 for the atom's period p.
+    The period comes from the ball method: a disc of radius r around z is iterated along with the
+    orbit of the search point, starting from the search disc itself,
+        z' = z*z + c, r' = r*(2*|z| + r) + radius
+    and the first iteration whose disc contains 0 gives the period of the lowest period atom that
+    can have its nucleus in the search disc.
+    The nucleus is then refined with Newton's method, c' = c - z_p/(dz_p/dc), with z computed in
+    the high precision arithmetic and dz/dc in FloatExp, which only limits how fast the steps
+    converge.  Once the steps are lost in the precision, the size of the minibrot is estimated as
+    |1/(b*l^2)|, with l = dz_p/dz_1 and b = the sum of 1/(dz_i/dz_1) over 1 <= i < p, which is
+    the scale of the minibrot relative to the whole Mandelbrot set, and the precision is raised
+    and the nucleus refined again if it isn't enough to show the minibrot.
+*/
+
+use crate::{ Limb, HPData, HpFixed, FloatExp, MAX_DECIMAL_DIGITS, ReferenceOrbit, sq, add, negate, multiply, t_to_floatexp, floatexp_to_t, bailout_chunks, escaped_hp };
+use crate::series::{ Complex, complex_mul, complex_add, complex_abs };
+
+// orbits that get this far out aren't going back to 0
+const NUCLEUS_BAILOUT: f64 = 16.0;
+
+// Newton's method converges quadratically, so this is far more steps than it needs at any precision
+const MAX_NEWTON_STEPS: usize = 64;
+
+// bits of precision beyond the size of the minibrot, for views of it and a good way into it
+const GUARD_BITS: i64 = 64;
+
+pub struct Nucleus<T> {
+    pub x: HpFixed<T>,
+    pub y: HpFixed<T>,
+    pub period: usize,
+    // the approximate size of the minibrot relative to the whole Mandelbrot set
+    pub size: FloatExp,
+}
+
+// the nucleus of the lowest period atom that may be within radius of (x, y), with enough precision for views of its minibrot;
+// None if there isn't one, or if the radius or the minibrot needs more than max_nucleus_chunks
+pub fn find_nucleus<T>(x: &HpFixed<T>, y: &HpFixed<T>, radius: FloatExp, max_period: i32) -> Option<Nucleus<T>>
+where T: Limb,
+{
+    let mut chunks = x.len().max(y.len()).max(chunks_for_size::<T>(radius));
+    if chunks > max_nucleus_chunks::<T>() {
+        return None;
+    }
+    let (mut cx, mut cy) = (x.with_chunks(chunks).into_chunks(), y.with_chunks(chunks).into_chunks());
+    let period = find_period(&cx, &cy, radius, max_period)?;
+
+    loop {
+        (cx, cy) = refine_nucleus(&cx, &cy, period)?;
+        let size = minibrot_size(&mut HPData::new(chunks), &cx, &cy, period)?;
+        let needed = chunks_for_size::<T>(size);
+        if needed <= chunks {
+            return Some(Nucleus { x: HpFixed::from_chunks(cx), y: HpFixed::from_chunks(cy), period, size });
+        }
+        if needed > max_nucleus_chunks::<T>() {
+            return None;
+        }
+        chunks = needed;
+        cx.resize(chunks, T::zero());
+        cy.resize(chunks, T::zero());
+    }
+}
+
+// chunks for points size apart, plus GUARD_BITS
+pub fn chunks_for_size<T>(size: FloatExp) -> usize
+where T: Limb,
+{
+    let bits = (GUARD_BITS - size.exponent()).max(0) as usize;
+    1 + bits.div_ceil(T::HALF_BITS)
+}
+
+// the most chunks find_nucleus works at; a nucleus needing more couldn't be given as a decimal string anyway
+pub fn max_nucleus_chunks<T>() -> usize
+where T: Limb,
+{
+    HpFixed::<T>::chunks_for_digits(MAX_DECIMAL_DIGITS)
+}
+
+// the ball method: the period of the lowest period atom whose nucleus may be within radius of (x, y)
+pub fn find_period<T>(x: &[T], y: &[T], radius: FloatExp, max_period: i32) -> Option<usize>
+where T: Limb,
+{
+    let orbit = ReferenceOrbit::new(&mut HPData::new(x.len()), x, y, max_period, NUCLEUS_BAILOUT);
+    let two = FloatExp::from(2.0);
+
+    // the disc around z_1 = c is the search disc
+    let mut r = radius;
+    for (n, &(zx, zy)) in orbit.z.iter().enumerate().skip(1) {
+        let z_abs = complex_abs((FloatExp::from(zx), FloatExp::from(zy)));
+        if z_abs < r {
+            return Some(n);
+        }
+        r = r*(two*z_abs + r) + radius;
+    }
+    None
+}
+
+// Newton's method for the nucleus of period period near (x, y), at the precision of x and y
+pub fn refine_nucleus<T>(x: &[T], y: &[T], period: usize) -> Option<(Vec<T>, Vec<T>)>
+where T: Limb,
+{
+    let chunks = x.len();
+    let mut hp_data = HPData::new(chunks);
+    let (mut cx, mut cy) = (x.to_vec(), y.to_vec());
+    let mut work = vec![T::zero(); chunks];
+    let (one, two) = (FloatExp::from(1.0), FloatExp::from(2.0));
+    // the last fraction bit
+    let precision = FloatExp::new(1.0, -(((chunks - 1)*T::HALF_BITS) as i64));
+
+    let mut last_step = None;
+    for _ in 0..MAX_NEWTON_STEPS {
+        // der = dz/dc = 2*z*der + 1, from z = der = 0
+        let mut der = (FloatExp::zero(), FloatExp::zero());
+        let mut z = der;
+        let escaped = !iterate(&mut hp_data, &cx, &cy, period, | zn | {
+            der = complex_add(complex_mul((two*z.0, two*z.1), der), (one, FloatExp::zero()));
+            z = zn;
+        });
+        if escaped {
+            return None;
+        }
+
+        // c -= z/der
+        let step = complex_div((-z.0, -z.1), der);
+        work.copy_from_slice(&cx);
+        add(&work, &floatexp_to_t(step.0, chunks), &mut cx);
+        work.copy_from_slice(&cy);
+        add(&work, &floatexp_to_t(step.1, chunks), &mut cy);
+
+        // once the steps stop shrinking well past half the precision, they're just rounding
+        let step = complex_abs(step);
+        match last_step {
+            Some(last_step) if step*two >= last_step && step.sq() < precision => return Some((cx, cy)),
+            _ => last_step = Some(step),
+        }
+    }
+    None
+}
+
+// the approximate size of the minibrot with nucleus (x, y) and period period
+pub fn minibrot_size<T>(hp_data: &mut HPData<T>, x: &[T], y: &[T], period: usize) -> Option<FloatExp>
+where T: Limb,
+{
+    // l = dz_i/dz_1 = the product of 2*z over 1 <= i < p, b = 1 + the sum of 1/l
+    let one = (FloatExp::from(1.0), FloatExp::zero());
+    let two = FloatExp::from(2.0);
+    let (mut l, mut b) = (one, one);
+    let bounded = iterate(hp_data, x, y, period - 1, | z | {
+        l = complex_mul((two*z.0, two*z.1), l);
+        b = complex_add(b, complex_div(one, l));
+    });
+    bounded.then(|| one.0/complex_abs(complex_mul(b, complex_mul(l, l))))
+}
+
+// call f with each z_n of the orbit of (x, y) for 1 <= n <= iterations, unless it escapes first
+fn iterate<T, F>(hp_data: &mut HPData<T>, x: &[T], y: &[T], iterations: usize, mut f: F) -> bool
+where T: Limb,
+    F: FnMut(Complex),
+{
+    let bailout = bailout_chunks(NUCLEUS_BAILOUT);
+    hp_data.zx.copy_from_slice(x);
+    hp_data.zy.copy_from_slice(y);
+    for n in 1..=iterations {
+        f((t_to_floatexp(&hp_data.zx), t_to_floatexp(&hp_data.zy)));
+        if n == iterations {
+            break;
+        }
+
+        // z = z*z + c, as in ReferenceOrbit::new
+        sq(&hp_data.zx, &mut hp_data.work3, &mut hp_data.work1);
+        sq(&hp_data.zy, &mut hp_data.work3, &mut hp_data.work2);
+        add(&hp_data.work1, &hp_data.work2, &mut hp_data.work3);
+        if escaped_hp(&hp_data.work3, bailout) {
+            return false;
+        }
+        add(&hp_data.zx, &hp_data.zx, &mut hp_data.work4);
+        negate(&hp_data.work2, &mut hp_data.work3);
+        add(&hp_data.work1, &hp_data.work3, &mut hp_data.work2);
+        add(&hp_data.work2, x, &mut hp_data.zx);
+        multiply(&hp_data.work4, &hp_data.zy, &mut hp_data.work1, &mut hp_data.work3, &mut hp_data.work2);
+        add(&hp_data.work2, y, &mut hp_data.zy);
+    }
+    true
+}
+
+fn complex_div(a: Complex, b: Complex) -> Complex {
+    let norm = b.0.sq() + b.1.sq();
+    ((a.0*b.0 + a.1*b.1)/norm, (a.1*b.0 - a.0*b.1)/norm)
+}
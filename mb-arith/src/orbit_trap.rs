@@ -0,0 +1,234 @@
+/*
+    Orbit traps
+    By Bill Wood
+
+    An orbit trap is a shape in the plane of z.  Along with the iteration count, these kernels
+    return how close the orbit of each point came to the trap, and the iteration at which it came
+    closest, for coloring by trap distance instead of by the iteration count.  Every z of the orbit
+    is tested, from z = c up to and including the z that escaped.
+    Periodicity checking doesn't change the results: once the orbit repeats exactly, it can only
+    revisit points that have already been tested.  The main cardioid and period 2 bulb aren't
+    skipped, since their points are often the most interesting ones to trap.
+    The trap is tested in f64, which is plenty since |z| < the bailout radius.
+*/
+
+use core::cmp::PartialEq;
+use crate::{ Limb, HPData, DoubleDouble, Formula, FormulaKind, Mandelbrot, BurningShip, Tricorn, Celtic, sq, add, t_to_f64,
+    bailout_chunks, escaped_hp, periodicity_save };
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrbitTrap {
+    // the point (x, y)
+    Point { x: f64, y: f64 },
+    // the line through (x, y) at angle radians from the real axis
+    Line { x: f64, y: f64, angle: f64 },
+    // the horizontal and vertical lines through (x, y)
+    Cross { x: f64, y: f64 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrapHit {
+    // the smallest distance from the orbit to the trap
+    pub distance: f64,
+    // the iteration at which the orbit came closest, 0 being z = c
+    pub iteration: i32,
+}
+
+impl Default for TrapHit {
+    fn default() -> TrapHit {
+        TrapHit { distance: f64::INFINITY, iteration: 0 }
+    }
+}
+
+// the trap, with the line's angle turned into a unit normal once per point instead of once per iteration
+#[derive(Clone, Copy)]
+enum Trap {
+    Point { x: f64, y: f64 },
+    Line { x: f64, y: f64, nx: f64, ny: f64 },
+    Cross { x: f64, y: f64 },
+}
+
+impl From<&OrbitTrap> for Trap {
+    fn from(trap: &OrbitTrap) -> Trap {
+        match *trap {
+            OrbitTrap::Point { x, y } => Trap::Point { x, y },
+            OrbitTrap::Line { x, y, angle } => {
+                let (sin, cos) = angle.sin_cos();
+                Trap::Line { x, y, nx: -sin, ny: cos }
+            },
+            OrbitTrap::Cross { x, y } => Trap::Cross { x, y },
+        }
+    }
+}
+
+impl Trap {
+    #[inline]
+    fn distance(&self, zx: f64, zy: f64) -> f64 {
+        match *self {
+            Trap::Point { x, y } => ((zx - x)*(zx - x) + (zy - y)*(zy - y)).sqrt(),
+            Trap::Line { x, y, nx, ny } => ((zx - x)*nx + (zy - y)*ny).abs(),
+            Trap::Cross { x, y } => (zx - x).abs().min((zy - y).abs()),
+        }
+    }
+
+    // keep the first iteration with the smallest distance
+    #[inline]
+    fn test(&self, zx: f64, zy: f64, iteration: i32, hit: &mut TrapHit) {
+        let distance = self.distance(zx, zy);
+        if distance < hit.distance {
+            *hit = TrapHit { distance, iteration };
+        }
+    }
+}
+
+// *** low precision *** //
+// returns the iteration count, the final value of zx*zx + zy*zy for smooth_iteration_count, and the trap hit
+#[inline]
+pub fn count_iterations_formula_trap<F: Formula>(formula: &F, x: f64, y: f64, trap: &OrbitTrap, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64, TrapHit) {
+    let trap = Trap::from(trap);
+    let mut hit = TrapHit::default();
+    let mut count = 0;
+    let mut zx = x;
+    let mut zy = y;
+    trap.test(zx, zy, count, &mut hit);
+    let mut norm = zx*zx + zy*zy;
+    let (mut check_zx, mut check_zy) = (zx, zy);
+
+    while count < max_iterations && norm < bailout {
+        (zx, zy) = formula.step(zx, zy, x, y);
+        count += 1;
+        trap.test(zx, zy, count, &mut hit);
+        norm = zx*zx + zy*zy;
+
+        if periodicity {
+            if zx == check_zx && zy == check_zy {
+                return (-1, 0.0, hit);
+            }
+            if periodicity_save(count) {
+                (check_zx, check_zy) = (zx, zy);
+            }
+        }
+    }
+
+    if count < max_iterations {
+        (count, norm, hit)
+    } else {
+        (-1, 0.0, hit)
+    }
+}
+
+
+// *** double-double precision *** //
+#[inline]
+pub fn count_iterations_dd_formula_trap<F: Formula>(formula: &F, x: DoubleDouble, y: DoubleDouble, trap: &OrbitTrap, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64, TrapHit) {
+    let trap = Trap::from(trap);
+    let mut hit = TrapHit::default();
+    let mut count = 0;
+    let mut zx = x;
+    let mut zy = y;
+    trap.test(zx.hi(), zy.hi(), count, &mut hit);
+
+    // the low parts are too small to matter for the bailout test
+    let mut norm = zx.hi()*zx.hi() + zy.hi()*zy.hi();
+    let (mut check_zx, mut check_zy) = (zx, zy);
+    while count < max_iterations && norm < bailout {
+        (zx, zy) = formula.step_dd(zx, zy, x, y);
+        count += 1;
+        trap.test(zx.hi(), zy.hi(), count, &mut hit);
+        norm = zx.hi()*zx.hi() + zy.hi()*zy.hi();
+
+        if periodicity {
+            if zx == check_zx && zy == check_zy {
+                return (-1, 0.0, hit);
+            }
+            if periodicity_save(count) {
+                (check_zx, check_zy) = (zx, zy);
+            }
+        }
+    }
+
+    if count < max_iterations {
+        (count, norm, hit)
+    } else {
+        (-1, 0.0, hit)
+    }
+}
+
+
+// *** high precision *** //
+// same loop as count_iterations_hp_formula
+pub fn count_iterations_hp_formula_trap<F, T>(formula: &F, hp_data: &mut HPData<T>, x: &[T], y: &[T], trap: &OrbitTrap, max_iterations: i32, bailout: f64) -> (i32, f64, TrapHit)
+where F: Formula,
+    T: Limb,
+{
+    let trap = Trap::from(trap);
+    let mut hit = TrapHit::default();
+    let mut count = 0;
+    hp_data.zx.copy_from_slice(x);
+    hp_data.zy.copy_from_slice(y);
+    hp_data.check_zx.copy_from_slice(x);
+    hp_data.check_zy.copy_from_slice(y);
+
+    let bailout = bailout_chunks(bailout);
+
+    // while count < max_iterations && zx*zx + zy*zy < bailout {
+    while count < max_iterations {
+        trap.test(t_to_f64(&hp_data.zx), t_to_f64(&hp_data.zy), count, &mut hit);
+        sq(&hp_data.zx, &mut hp_data.work3, &mut hp_data.work1);
+        sq(&hp_data.zy, &mut hp_data.work3, &mut hp_data.work2);
+        add(&hp_data.work1, &hp_data.work2, &mut hp_data.work3);
+        if escaped_hp(&hp_data.work3, bailout) {
+            return (count, t_to_f64(&hp_data.work3), hit);
+        }
+
+        formula.step_hp(hp_data, x, y);
+        count += 1;
+
+        if hp_data.periodicity {
+            if hp_data.zx == hp_data.check_zx && hp_data.zy == hp_data.check_zy {
+                return (-1, 0.0, hit);
+            }
+            if periodicity_save(count) {
+                hp_data.check_zx.copy_from_slice(&hp_data.zx);
+                hp_data.check_zy.copy_from_slice(&hp_data.zy);
+            }
+        }
+    }
+    trap.test(t_to_f64(&hp_data.zx), t_to_f64(&hp_data.zy), count, &mut hit);
+    (-1, 0.0, hit)
+}
+
+
+// *** traps by formula name *** //
+impl FormulaKind {
+    #[inline]
+    pub fn count_iterations_trap(self, x: f64, y: f64, trap: &OrbitTrap, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64, TrapHit) {
+        match self {
+            FormulaKind::Mandelbrot => count_iterations_formula_trap(&Mandelbrot, x, y, trap, max_iterations, bailout, periodicity),
+            FormulaKind::BurningShip => count_iterations_formula_trap(&BurningShip, x, y, trap, max_iterations, bailout, periodicity),
+            FormulaKind::Tricorn => count_iterations_formula_trap(&Tricorn, x, y, trap, max_iterations, bailout, periodicity),
+            FormulaKind::Celtic => count_iterations_formula_trap(&Celtic, x, y, trap, max_iterations, bailout, periodicity),
+        }
+    }
+
+    #[inline]
+    pub fn count_iterations_dd_trap(self, x: DoubleDouble, y: DoubleDouble, trap: &OrbitTrap, max_iterations: i32, bailout: f64, periodicity: bool) -> (i32, f64, TrapHit) {
+        match self {
+            FormulaKind::Mandelbrot => count_iterations_dd_formula_trap(&Mandelbrot, x, y, trap, max_iterations, bailout, periodicity),
+            FormulaKind::BurningShip => count_iterations_dd_formula_trap(&BurningShip, x, y, trap, max_iterations, bailout, periodicity),
+            FormulaKind::Tricorn => count_iterations_dd_formula_trap(&Tricorn, x, y, trap, max_iterations, bailout, periodicity),
+            FormulaKind::Celtic => count_iterations_dd_formula_trap(&Celtic, x, y, trap, max_iterations, bailout, periodicity),
+        }
+    }
+
+    pub fn count_iterations_hp_trap<T>(self, hp_data: &mut HPData<T>, x: &[T], y: &[T], trap: &OrbitTrap, max_iterations: i32, bailout: f64) -> (i32, f64, TrapHit)
+    where T: Limb,
+    {
+        match self {
+            FormulaKind::Mandelbrot => count_iterations_hp_formula_trap(&Mandelbrot, hp_data, x, y, trap, max_iterations, bailout),
+            FormulaKind::BurningShip => count_iterations_hp_formula_trap(&BurningShip, hp_data, x, y, trap, max_iterations, bailout),
+            FormulaKind::Tricorn => count_iterations_hp_formula_trap(&Tricorn, hp_data, x, y, trap, max_iterations, bailout),
+            FormulaKind::Celtic => count_iterations_hp_formula_trap(&Celtic, hp_data, x, y, trap, max_iterations, bailout),
+        }
+    }
+}
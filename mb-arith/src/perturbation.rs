@@ -0,0 +1,190 @@
+/*
+    Perturbation theory for deep zooms
+    By Bill Wood
+
+    One reference orbit Z is computed in high precision, then every other pixel c = C + dc
+    is iterated as an f64 delta dz against it:
+        z = Z + dz
+        dz' = 2*Z*dz + dz*dz + dc
+    The reference starts at Z = 0, so a pixel can always be "rebased" onto the start of the
+    reference orbit by setting dz = z, with no loss of precision.
+    The deltas can be f64, or FloatExp for zooms past the f64 range.
+*/
+
+use std::ops::{ Sub, Add, Mul };
+use crate::{ Limb, HPData, FloatExp, sq, add, negate, multiply, t_to_f64, bailout_chunks, escaped_hp, pixel_distance };
+
+// number types the pixel deltas can be iterated in
+pub trait Delta: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + PartialOrd {
+    fn from_f64(x: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn from_floatexp(x: FloatExp) -> Self;
+    fn to_floatexp(self) -> FloatExp;
+}
+
+// pixel spacings with a binary exponent below this should use FloatExp deltas, since f64
+// deltas (and their squares) start to lose precision near the bottom of the f64 range
+pub const F64_DELTA_MIN_EXPONENT: i64 = -960;
+
+impl Delta for f64 {
+    fn from_f64(x: f64) -> f64 {
+        x
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_floatexp(x: FloatExp) -> f64 {
+        x.to_f64()
+    }
+
+    fn to_floatexp(self) -> FloatExp {
+        FloatExp::from(self)
+    }
+}
+
+pub struct ReferenceOrbit {
+    // z[n] is the reference orbit value Z_n rounded to f64, with z[0] = 0 and z[1] = the reference point
+    pub(crate) z: Vec<(f64, f64)>,
+}
+
+impl ReferenceOrbit {
+    // compute the reference orbit of (x, y) using the high precision arithmetic; same loop as count_iterations_hp
+    pub fn new<T>(hp_data: &mut HPData<T>, x: &[T], y: &[T], max_iterations: i32, bailout: f64) -> ReferenceOrbit
+    where T: Limb,
+    {
+        let mut z = vec![(0.0, 0.0)];
+        hp_data.zx.copy_from_slice(x);
+        hp_data.zy.copy_from_slice(y);
+
+        let bailout = bailout_chunks(bailout);
+
+        for _ in 0..max_iterations {
+            z.push((t_to_f64(&hp_data.zx), t_to_f64(&hp_data.zy)));
+
+            sq(&hp_data.zx, &mut hp_data.work3, &mut hp_data.work1);
+            sq(&hp_data.zy, &mut hp_data.work3, &mut hp_data.work2);
+            add(&hp_data.work1, &hp_data.work2, &mut hp_data.work3);
+            if escaped_hp(&hp_data.work3, bailout) {
+                // escaped; pixels that get this far are rebased
+                break;
+            }
+
+            add(&hp_data.zx, &hp_data.zx, &mut hp_data.work4);
+            negate(&hp_data.work2, &mut hp_data.work3);
+            add(&hp_data.work1, &hp_data.work3, &mut hp_data.work2);
+            add(&hp_data.work2, x, &mut hp_data.zx);
+            multiply(&hp_data.work4, &hp_data.zy, &mut hp_data.work1, &mut hp_data.work3, &mut hp_data.work2);
+            add(&hp_data.work2, y, &mut hp_data.zy);
+        }
+        ReferenceOrbit { z }
+    }
+}
+
+// count iterations for the pixel at offset (dcx, dcy) from the reference point; returns the same counts as
+// count_iterations_hp, apart from pixels whose final zx*zx + zy*zy rounds to the other side of the bailout,
+// which can escape an iteration sooner or later (see tests/perturbation.rs)
+pub fn count_iterations_perturbed<D: Delta>(orbit: &ReferenceOrbit, dcx: D, dcy: D, max_iterations: i32, bailout: f64) -> i32 {
+    count_iterations_perturbed_smooth(orbit, dcx, dcy, max_iterations, bailout).0
+}
+
+// also returns the final value of zx*zx + zy*zy, for smooth_iteration_count
+#[inline]
+pub fn count_iterations_perturbed_smooth<D: Delta>(orbit: &ReferenceOrbit, dcx: D, dcy: D, max_iterations: i32, bailout: f64) -> (i32, f64) {
+    let zero = D::from_f64(0.0);
+    perturbed_smooth_from(orbit, 0, zero, zero, dcx, dcy, max_iterations, bailout)
+}
+
+// count_iterations_perturbed_smooth from iteration m of the reference orbit, where the pixel's delta is dz,
+// for a pixel that hasn't been rebased
+#[inline]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn perturbed_smooth_from<D: Delta>(orbit: &ReferenceOrbit, mut m: usize, mut dzx: D, mut dzy: D, dcx: D, dcy: D, max_iterations: i32, bailout: f64) -> (i32, f64) {
+    let z = &orbit.z;
+    let last = z.len() - 1;
+    let two = D::from_f64(2.0);
+    let bailout = D::from_f64(bailout);
+    let mut count = m as i32;
+
+    while count < max_iterations {
+        // dz = 2*Z*dz + dz*dz + dc
+        let zx = D::from_f64(z[m].0);
+        let zy = D::from_f64(z[m].1);
+        let new_dzx = (two*zx + dzx)*dzx - (two*zy + dzy)*dzy + dcx;
+        dzy = two*((zx + dzx)*dzy + zy*dzx) + dcy;
+        dzx = new_dzx;
+        m += 1;
+
+        // while count < max_iterations && zx*zx + zy*zy < bailout {
+        let fx = D::from_f64(z[m].0) + dzx;
+        let fy = D::from_f64(z[m].1) + dzy;
+        let norm = fx*fx + fy*fy;
+        if norm >= bailout {
+            return (count, norm.to_f64());
+        }
+
+        // glitch detection: once the pixel's orbit is closer to 0 than to the reference orbit, dz has
+        // lost precision relative to z, so rebase onto the start of the reference; also rebase when
+        // the reference escaped before this pixel did
+        if norm < dzx*dzx + dzy*dzy || m == last {
+            dzx = fx;
+            dzy = fy;
+            m = 0;
+        }
+        count += 1;
+    }
+    (-1, 0.0)
+}
+
+// same as count_iterations_perturbed_smooth, also returning the distance in pixels as in count_iterations_hp_distance
+pub fn count_iterations_perturbed_distance<D: Delta>(orbit: &ReferenceOrbit, dcx: D, dcy: D, dx: FloatExp, max_iterations: i32, bailout: f64) -> (i32, f64, f64) {
+    let zero = D::from_f64(0.0);
+    // dz/dc of the pixel's orbit, starting from the z = c of iteration 0
+    let der = (FloatExp::from(1.0), FloatExp::zero());
+    perturbed_distance_from(orbit, 0, zero, zero, der, dcx, dcy, dx, max_iterations, bailout)
+}
+
+// count_iterations_perturbed_distance from iteration m of the reference orbit, as in perturbed_smooth_from;
+// der is dz/dc of the z that iteration m + 1 produces
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn perturbed_distance_from<D: Delta>(orbit: &ReferenceOrbit, mut m: usize, mut dzx: D, mut dzy: D, der: (FloatExp, FloatExp), dcx: D, dcy: D, dx: FloatExp, max_iterations: i32, bailout: f64) -> (i32, f64, f64) {
+    let z = &orbit.z;
+    let last = z.len() - 1;
+    let two = D::from_f64(2.0);
+    let bailout = D::from_f64(bailout);
+    let mut count = m as i32;
+
+    let (one_fe, two_fe) = (FloatExp::from(1.0), FloatExp::from(2.0));
+    let (mut derx, mut dery) = der;
+
+    while count < max_iterations {
+        // dz = 2*Z*dz + dz*dz + dc
+        let zx = D::from_f64(z[m].0);
+        let zy = D::from_f64(z[m].1);
+        let new_dzx = (two*zx + dzx)*dzx - (two*zy + dzy)*dzy + dcx;
+        dzy = two*((zx + dzx)*dzy + zy*dzx) + dcy;
+        dzx = new_dzx;
+        m += 1;
+
+        let fx = D::from_f64(z[m].0) + dzx;
+        let fy = D::from_f64(z[m].1) + dzy;
+        let norm = fx*fx + fy*fy;
+        if norm >= bailout {
+            let norm = norm.to_f64();
+            return (count, norm, pixel_distance(norm, ((derx*derx + dery*dery)*dx*dx).to_f64()));
+        }
+
+        // der = 2*z*der + 1
+        let (zx, zy) = (FloatExp::from(fx.to_f64()), FloatExp::from(fy.to_f64()));
+        (derx, dery) = (two_fe*(zx*derx - zy*dery) + one_fe, two_fe*(zx*dery + zy*derx));
+
+        if norm < dzx*dzx + dzy*dzy || m == last {
+            dzx = fx;
+            dzy = fy;
+            m = 0;
+        }
+        count += 1;
+    }
+    (-1, 0.0, 0.0)
+}
@@ -0,0 +1,53 @@
+/*
+    Automatic precision selection
+    By Bill Wood
+
+    choose_precision picks the cheapest representation that can still tell neighboring pixels
+    apart, from the size of the coordinates in the view and the spacing of its pixels.  f64 and
+    double-double are floating point, so they need bits for the integer part of the coordinates
+    as well as for the pixel spacing; orbits reach |z| = 2 whatever the view, so coordinates are
+    taken to be at least that big.  The f64 cutoff comes out at a pixel spacing of about 1e-15,
+    where the client switches to high precision.  High precision is fixed point, so only the
+    fraction bits count, plus guard bits for the rounding error that builds up over the orbit.
+*/
+
+use std::fmt;
+use crate::FloatExp;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Precision {
+    F64,
+    DoubleDouble,
+    // high precision with this many 16 bit chunks, as sent by the client, including the integer chunk
+    HP { u32_chunks: usize },
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Precision::F64 => write!(f, "f64"),
+            Precision::DoubleDouble => write!(f, "doubleDouble"),
+            Precision::HP { u32_chunks } => write!(f, "hp:{u32_chunks}"),
+        }
+    }
+}
+
+// mantissa bits less 2 guard bits; double-double arithmetic loses a couple of its 106 bits
+const F64_BITS: i64 = 53 - 2;
+const DOUBLE_DOUBLE_BITS: i64 = 104 - 2;
+const HP_GUARD_BITS: i64 = 24;
+
+// extent is the largest |x| or |y| in the view, and pixel_size the smaller of dx and dy
+pub fn choose_precision(extent: f64, pixel_size: FloatExp) -> Precision {
+    let integer_bits = extent.max(2.0).log2().ceil() as i64;
+
+    // pixel_size >= 2^(exponent - 1)
+    let fraction_bits = 1 - pixel_size.exponent();
+    if integer_bits + fraction_bits <= F64_BITS {
+        Precision::F64
+    } else if integer_bits + fraction_bits <= DOUBLE_DOUBLE_BITS {
+        Precision::DoubleDouble
+    } else {
+        Precision::HP { u32_chunks: 1 + ((fraction_bits + HP_GUARD_BITS) as usize).div_ceil(16) }
+    }
+}
@@ -0,0 +1,136 @@
+/*
+    Series approximation for perturbation
+    By Bill Wood
+
+    For the pixels near a reference orbit Z, the perturbation delta dz of each iteration is a
+    polynomial in the pixel's offset dc from the reference point:
+        dz_n = a1_n*dc + a2_n*dc^2 + a3_n*dc^3 + ...
+    and substituting it into dz' = 2*Z*dz + dz*dz + dc gives the recurrence for the coefficients:
+        ak_n+1 = 2*Z_n*ak_n + the sum of ai_n*aj_n over i + j = k, plus 1 for a1
+    starting from all 0.  While the series cut off after a few terms is accurate for every pixel of
+    the view, the iterations up to there can be skipped by evaluating the series once per pixel and
+    carrying on with perturbation from that iteration.
+    The series is used up to the last iteration where its last term is still tiny next to its first
+    over the whole view, and where no pixel can have escaped or needed rebasing yet.  The
+    coefficients quickly grow past the f64 range, so they're FloatExp.
+*/
+
+use crate::{ FloatExp, Delta, ReferenceOrbit, perturbed_smooth_from, perturbed_distance_from };
+
+// number of terms of the series
+pub const SERIES_TERMS: usize = 8;
+
+// the largest the last term can be as a fraction of the first, over the whole view
+const SERIES_TOLERANCE: f64 = 1.0e-12;
+
+pub(crate) type Complex = (FloatExp, FloatExp);
+
+#[inline]
+pub(crate) fn complex_mul(a: Complex, b: Complex) -> Complex {
+    (a.0*b.0 - a.1*b.1, a.0*b.1 + a.1*b.0)
+}
+
+#[inline]
+pub(crate) fn complex_add(a: Complex, b: Complex) -> Complex {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+pub(crate) fn complex_abs(a: Complex) -> FloatExp {
+    (a.0.sq() + a.1.sq()).sqrt()
+}
+
+pub struct SeriesApproximation {
+    // the coefficients a1, a2, ... at iteration skip
+    a: Vec<Complex>,
+    skip: usize,
+}
+
+impl SeriesApproximation {
+    // radius is the largest |dc| of the pixels the series will be used for; terms must be at least 2
+    pub fn new(orbit: &ReferenceOrbit, terms: usize, radius: FloatExp, bailout: f64) -> SeriesApproximation {
+        assert!(terms >= 2, "series approximation needs at least 2 terms");
+        let z = &orbit.z;
+        let zero = (FloatExp::zero(), FloatExp::zero());
+        let (one, two) = (FloatExp::from(1.0), FloatExp::from(2.0));
+        let (tolerance, bailout) = (FloatExp::from(SERIES_TOLERANCE), FloatExp::from(bailout));
+
+        // radius^k for each term
+        let radius_powers: Vec<FloatExp> = (0..terms).scan(one, | r, _ | { *r = *r*radius; Some(*r) }).collect();
+
+        let mut a = vec![zero; terms];
+        let mut next = vec![zero; terms];
+        let mut series = SeriesApproximation { a: a.clone(), skip: 0 };
+
+        // the reference orbit has to go on past the skipped iterations
+        for n in 0..z.len().saturating_sub(2) {
+            let two_z = (two*FloatExp::from(z[n].0), two*FloatExp::from(z[n].1));
+            for k in 0..terms {
+                next[k] = complex_mul(two_z, a[k]);
+                for i in 0..k {
+                    next[k] = complex_add(next[k], complex_mul(a[i], a[k - 1 - i]));
+                }
+            }
+            next[0].0 = next[0].0 + one;
+            core::mem::swap(&mut a, &mut next);
+
+            // the size of each term over the whole view, and a bound on |dz| at iteration n + 1
+            let first = complex_abs(a[0])*radius_powers[0];
+            let last = complex_abs(a[terms - 1])*radius_powers[terms - 1];
+            let bound = a.iter().zip(&radius_powers).fold(FloatExp::zero(), | bound, (&a, &r) | bound + complex_abs(a)*r);
+
+            // a pixel escapes once |Z + dz|^2 >= bailout, and is rebased once |Z + dz| < |dz|
+            let z_abs = complex_abs((FloatExp::from(z[n + 1].0), FloatExp::from(z[n + 1].1)));
+            let valid = last < tolerance*first && z_abs > two*bound && (z_abs + bound).sq() < bailout;
+            if !valid {
+                break;
+            }
+            series.a.copy_from_slice(&a);
+            series.skip = n + 1;
+        }
+        series
+    }
+
+    // the number of iterations the series skips
+    pub fn skipped(&self) -> i32 {
+        self.skip as i32
+    }
+
+    // dz at iteration skip
+    fn dz(&self, dc: Complex) -> Complex {
+        let mut s = self.a[self.a.len() - 1];
+        for &a in self.a.iter().rev().skip(1) {
+            s = complex_add(a, complex_mul(dc, s));
+        }
+        complex_mul(dc, s)
+    }
+
+    // the derivative of dz at iteration skip by dc
+    fn derivative(&self, dc: Complex) -> Complex {
+        let scale = | k: usize, a: Complex | (FloatExp::from(k as f64)*a.0, FloatExp::from(k as f64)*a.1);
+        let terms = self.a.len();
+        let mut s = scale(terms, self.a[terms - 1]);
+        for k in (0..terms - 1).rev() {
+            s = complex_add(scale(k + 1, self.a[k]), complex_mul(dc, s));
+        }
+        s
+    }
+}
+
+// count_iterations_perturbed_smooth, skipping the iterations covered by series
+pub fn count_iterations_series_smooth<D: Delta>(orbit: &ReferenceOrbit, series: &SeriesApproximation, dcx: D, dcy: D, max_iterations: i32, bailout: f64) -> (i32, f64) {
+    let (dzx, dzy) = series.dz((dcx.to_floatexp(), dcy.to_floatexp()));
+    perturbed_smooth_from(orbit, series.skip, D::from_floatexp(dzx), D::from_floatexp(dzy), dcx, dcy, max_iterations, bailout)
+}
+
+// count_iterations_perturbed_distance, skipping the iterations covered by series
+pub fn count_iterations_series_distance<D: Delta>(orbit: &ReferenceOrbit, series: &SeriesApproximation, dcx: D, dcy: D, dx: FloatExp, max_iterations: i32, bailout: f64) -> (i32, f64, f64) {
+    let dc = (dcx.to_floatexp(), dcy.to_floatexp());
+    let dz = series.dz(dc);
+
+    // der = 2*z*der + 1 for the z of iteration skip
+    let (derx, dery) = series.derivative(dc);
+    let (zx, zy) = (FloatExp::from(orbit.z[series.skip].0) + dz.0, FloatExp::from(orbit.z[series.skip].1) + dz.1);
+    let (one, two) = (FloatExp::from(1.0), FloatExp::from(2.0));
+    let der = (two*(zx*derx - zy*dery) + one, two*(zx*dery + zy*derx));
+    perturbed_distance_from(orbit, series.skip, D::from_floatexp(dz.0), D::from_floatexp(dz.1), der, dcx, dcy, dx, max_iterations, bailout)
+}
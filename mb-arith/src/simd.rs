@@ -0,0 +1,99 @@
+/*
+    Multi-pixel f64 kernel
+    By Bill Wood
+
+    count_iterations_smooth_lanes iterates LANES pixels of a row together, doing the same
+    arithmetic on every lane in straight-line loops that the compiler turns into SIMD instructions
+    (SSE2 or AVX on x86, simd128 on wasm).  A lane that escapes, or whose orbit repeats, is masked
+    out: its result is recorded and it no longer affects when the group stops, although it keeps
+    being iterated with the others.  The group stops once every lane is masked out, or at
+    max_iterations.
+    Each lane does exactly the same operations as count_iterations_smooth, in the same order, so the
+    results are identical to the scalar kernel's.
+*/
+
+use core::array::from_fn;
+use crate::{ in_main_cardioid_or_bulb, periodicity_save };
+
+// pixels per lane group: one AVX register or two SSE2 or simd128 registers of f64s
+pub const LANES: usize = 4;
+
+// same as count_iterations_smooth for each x, with the same y
+#[inline]
+pub fn count_iterations_smooth_lanes(x: [f64; LANES], y: f64, max_iterations: i32, bailout: f64, periodicity: bool) -> [(i32, f64); LANES] {
+    let mut results = [(-1, 0.0); LANES];
+
+    // lanes still iterating
+    let mut active: [bool; LANES] = from_fn(| l | !in_main_cardioid_or_bulb(x[l], y));
+    let mut zx = x;
+    let mut zy = [y; LANES];
+    let mut norm: [f64; LANES] = from_fn(| l | zx[l]*zx[l] + zy[l]*zy[l]);
+    let (mut check_zx, mut check_zy) = (zx, zy);
+
+    let mut count = 0;
+    while count < max_iterations {
+        for l in 0..LANES {
+            if active[l] && norm[l] >= bailout {
+                results[l] = (count, norm[l]);
+                active[l] = false;
+            }
+        }
+        if !active.contains(&true) {
+            break;
+        }
+
+        // zx = zx*zx - zy*zy + x; zy = 2.0*zx*zy + y
+        for l in 0..LANES {
+            (zx[l], zy[l]) = (zx[l]*zx[l] - zy[l]*zy[l] + x[l], 2.0*zx[l]*zy[l] + y);
+            norm[l] = zx[l]*zx[l] + zy[l]*zy[l];
+        }
+        count += 1;
+
+        if periodicity {
+            for l in 0..LANES {
+                active[l] &= (zx[l] != check_zx[l]) | (zy[l] != check_zy[l]);
+            }
+            if periodicity_save(count) {
+                (check_zx, check_zy) = (zx, zy);
+            }
+        }
+    }
+    results
+}
+
+// count_iterations_smooth for the points xmin + i*dx, y of a row of columns pixels, LANES at a time;
+// the last lane group is padded out with copies of the last pixel
+pub fn count_iterations_smooth_row(xmin: f64, dx: f64, columns: usize, y: f64, max_iterations: i32, bailout: f64, periodicity: bool) -> impl Iterator<Item = (i32, f64)> {
+    (0..columns).step_by(LANES).flat_map(move | i | {
+        let x = from_fn(| l | xmin + (i + l).min(columns - 1) as f64*dx);
+        count_iterations_smooth_lanes(x, y, max_iterations, bailout, periodicity).into_iter().take(columns - i)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ DEFAULT_BAILOUT, count_iterations_smooth };
+    use crate::test_grid::{ grid, COLUMNS, MAX_ITERATIONS };
+    use super::{ LANES, count_iterations_smooth_row };
+
+    #[test]
+    fn row_matches_scalar() {
+        let points: Vec<_> = grid().collect();
+        let dx = points[1].0 - points[0].0;
+        // widths that leave a partial lane group, or are under a lane group
+        for columns in [1, LANES - 1, LANES + 1, COLUMNS - 1, COLUMNS] {
+            for row in points.chunks(COLUMNS) {
+                let (xmin, y) = row[0];
+                for periodicity in [true, false] {
+                    let results: Vec<_> = count_iterations_smooth_row(xmin, dx, columns, y, MAX_ITERATIONS, DEFAULT_BAILOUT, periodicity).collect();
+                    assert_eq!(results.len(), columns);
+                    for (i, &actual) in results.iter().enumerate() {
+                        let x = xmin + i as f64*dx;
+                        assert_eq!(count_iterations_smooth(x, y, MAX_ITERATIONS, DEFAULT_BAILOUT, periodicity), actual,
+                            "columns = {}, x = {}, y = {}", columns, x, y);
+                    }
+                }
+            }
+        }
+    }
+}
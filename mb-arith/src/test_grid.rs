@@ -0,0 +1,30 @@
+/*
+    Test points
+    By Bill Wood
+
+    The unit tests that compare a kernel with the one it stands in for run both over this grid of
+    points covering the whole Mandelbrot set, so it has points that escape at once, points that take
+    a while, and points in the set, both inside and outside the main cardioid and period 2 bulb.
+*/
+
+use crate::{ Limb, FloatExp, floatexp_to_t };
+
+pub const COLUMNS: usize = 64;
+pub const ROWS: usize = 48;
+pub const MAX_ITERATIONS: i32 = 500;
+
+// x from -2.25 to 0.75 and y from 1.125 to -1.125, a third of the way into each grid cell so every
+// bit of the coordinates is used
+pub fn grid() -> impl Iterator<Item = (f64, f64)> {
+    let d = 3.0/COLUMNS as f64;
+    (0..ROWS).flat_map(move | i | (0..COLUMNS).map(move | j | {
+        (-2.25 + (j as f64 + 1.0/3.0)*d, 1.125 - (i as f64 + 1.0/3.0)*d)
+    }))
+}
+
+// the grid truncated to chunks chunks
+pub fn grid_hp<T>(chunks: usize) -> impl Iterator<Item = (Vec<T>, Vec<T>)>
+where T: Limb,
+{
+    grid().map(move | (x, y) | (floatexp_to_t(FloatExp::from(x), chunks), floatexp_to_t(FloatExp::from(y), chunks)))
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use mb_arith::*;
+use common::*;
+
+// BLA steps drop terms below the rounding of the perturbation steps, so the final zx*zx + zy*zy
+// stays as close to the HP kernel's as with perturbation alone; the counts are exact on this view
+const BLA_TOLERANCE: f64 = 1.0e-6;
+
+// the table has to cover the test pixel furthest from the center, 4 columns and 3 rows away
+fn table_radius(view: &View) -> FloatExp {
+    FloatExp::from(5.0*SPACING)*view.dx
+}
+
+#[test]
+fn bla_counts_match_hp() {
+    let view = View::new();
+    let orbit = view.reference_orbit();
+    let table = BlaTable::<f64>::new(&orbit, table_radius(&view));
+    let mut hp_data = HPData::new(view.chunks);
+    for offset in view.offsets() {
+        let expected = view.hp_smooth(&mut hp_data, offset);
+        let actual = count_iterations_bla_smooth(&orbit, &table, offset.0.to_f64(), offset.1.to_f64(), MAX_ITERATIONS, DEFAULT_BAILOUT);
+        assert_counts_match(expected, actual, BLA_TOLERANCE);
+    }
+}
+
+#[test]
+fn bla_floatexp_counts_match_hp() {
+    let view = View::new();
+    let orbit = view.reference_orbit();
+    let table = BlaTable::<FloatExp>::new(&orbit, table_radius(&view));
+    let mut hp_data = HPData::new(view.chunks);
+    for offset in view.offsets() {
+        let expected = view.hp_smooth(&mut hp_data, offset);
+        let actual = count_iterations_bla_smooth(&orbit, &table, offset.0, offset.1, MAX_ITERATIONS, DEFAULT_BAILOUT);
+        assert_counts_match(expected, actual, BLA_TOLERANCE);
+    }
+}
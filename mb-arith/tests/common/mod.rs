@@ -0,0 +1,62 @@
+// a small deep view shared by the tests that compare kernels against count_iterations_hp
+
+use mb_arith::*;
+
+// the center of 87-digits.xml in mb-rust-server, about 1.5e-72 across at 800x600
+const CENTER_X: &str = "-1.860460516298673172230333018784270044276096283022855952480965923931782897072038074843157";
+const CENTER_Y: &str = "0.000127117449884649210392863748142587684982284304423743585496794271755259268163325756400";
+pub const MAX_ITERATIONS: i32 = 50000;
+
+// 8x6 pixels spread over the view, 100 of its pixels apart
+pub const COLUMNS: i32 = 8;
+pub const ROWS: i32 = 6;
+pub const SPACING: f64 = 100.0;
+
+pub struct View {
+    pub chunks: usize,
+    pub x: Vec<u64>,
+    pub y: Vec<u64>,
+    // the width of a pixel of the full view
+    pub dx: FloatExp,
+}
+
+impl View {
+    pub fn new() -> View {
+        let x: HpFixed<u64> = CENTER_X.parse().unwrap();
+        let y: HpFixed<u64> = CENTER_Y.parse().unwrap();
+        let chunks = x.len().max(y.len());
+        View { chunks, x: x.with_chunks(chunks).into_chunks(), y: y.with_chunks(chunks).into_chunks(), dx: FloatExp::new(1.0, -248) }
+    }
+
+    // the offsets of the test pixels from the center
+    pub fn offsets(&self) -> Vec<(FloatExp, FloatExp)> {
+        let step = FloatExp::from(SPACING)*self.dx;
+        (0..ROWS).flat_map(| i | (0..COLUMNS).map(move | j | {
+            (FloatExp::from((j - COLUMNS/2) as f64)*step, FloatExp::from((ROWS/2 - i) as f64)*step)
+        })).collect()
+    }
+
+    // count_iterations_hp_smooth for the pixel at offset (dcx, dcy) from the center
+    pub fn hp_smooth(&self, hp_data: &mut HPData<u64>, (dcx, dcy): (FloatExp, FloatExp)) -> (i32, f64) {
+        let mut x = vec![0; self.chunks];
+        let mut y = vec![0; self.chunks];
+        add(&self.x, &floatexp_to_t(dcx, self.chunks), &mut x);
+        add(&self.y, &floatexp_to_t(dcy, self.chunks), &mut y);
+        count_iterations_hp_smooth(hp_data, &x, &y, MAX_ITERATIONS, DEFAULT_BAILOUT)
+    }
+
+    pub fn reference_orbit(&self) -> ReferenceOrbit {
+        ReferenceOrbit::new(&mut HPData::new(self.chunks), &self.x, &self.y, MAX_ITERATIONS, DEFAULT_BAILOUT)
+    }
+}
+
+// counts and final zx*zx + zy*zy from two kernels agree, apart from a pixel whose final value is within a
+// relative tolerance of the bailout, which can escape an iteration sooner or later
+pub fn assert_counts_match(expected: (i32, f64), actual: (i32, f64), tolerance: f64) {
+    if expected.0 == actual.0 {
+        return;
+    }
+    let first = if expected.0 < actual.0 { expected } else { actual };
+    assert!(expected.0 >= 0 && actual.0 >= 0 && (expected.0 - actual.0).abs() == 1 && first.1 < DEFAULT_BAILOUT*(1.0 + tolerance),
+        "counts {:?} and {:?} differ", expected, actual);
+}
@@ -0,0 +1,54 @@
+use mb_arith::*;
+
+// the real nucleus of period 3, the center of the largest minibrot on the real axis
+const PERIOD_3_X: &str = "-1.75487766624669276004950889635852869189460661777";
+
+// the sizes from |1/(b*l^2)|, worked out by hand for period 2 and to 50 digits for period 3
+const PERIOD_2_SIZE: f64 = 0.5;
+const PERIOD_3_SIZE: f64 = 0.019035515913132447;
+
+fn hp(s: &str) -> HpFixed<u64> {
+    s.parse().unwrap()
+}
+
+fn relative_error(actual: FloatExp, expected: FloatExp) -> f64 {
+    ((actual - expected)/expected).to_f64().abs()
+}
+
+#[test]
+fn finds_period_3_nucleus() {
+    let nucleus = find_nucleus(&hp("-1.75"), &hp("0"), FloatExp::from(0.01), 100).unwrap();
+    assert_eq!(nucleus.period, 3);
+    assert!((&nucleus.x - &hp(PERIOD_3_X).with_chunks(nucleus.x.len())).to_f64().abs() < 1.0e-18, "x = {}", nucleus.x);
+    assert!(nucleus.y.to_f64().abs() < 1.0e-18, "y = {}", nucleus.y);
+    assert!(relative_error(nucleus.size, FloatExp::from(PERIOD_3_SIZE)) < 1.0e-12, "size = {:?}", nucleus.size);
+}
+
+#[test]
+fn finds_period_2_nucleus() {
+    let nucleus = find_nucleus(&hp("-0.98"), &hp("0.01"), FloatExp::from(0.05), 100).unwrap();
+    assert_eq!(nucleus.period, 2);
+    assert!((&nucleus.x - &hp("-1").with_chunks(nucleus.x.len())).to_f64().abs() < 1.0e-18, "x = {}", nucleus.x);
+    assert!(nucleus.y.to_f64().abs() < 1.0e-18, "y = {}", nucleus.y);
+    assert!(relative_error(nucleus.size, FloatExp::from(PERIOD_2_SIZE)) < 1.0e-12, "size = {:?}", nucleus.size);
+}
+
+#[test]
+fn refines_to_known_nucleus() {
+    let chunks = 6;
+    let (x, y) = (hp("-1.75").with_chunks(chunks).into_chunks(), hp("0").with_chunks(chunks).into_chunks());
+    assert_eq!(find_period(&x, &y, FloatExp::from(0.01), 100), Some(3));
+    let (x, y) = refine_nucleus(&x, &y, 3).unwrap();
+    assert!((&HpFixed::from_chunks(x.clone()) - &hp(PERIOD_3_X).with_chunks(chunks)).to_f64().abs() < 1.0e-30);
+    let size = minibrot_size(&mut HPData::new(chunks), &x, &y, 3).unwrap();
+    assert!(relative_error(size, FloatExp::from(PERIOD_3_SIZE)) < 1.0e-15);
+}
+
+#[test]
+fn max_period_too_small() {
+    let (x, y) = (hp("-1.75").with_chunks(4).into_chunks(), hp("0").with_chunks(4).into_chunks());
+    assert_eq!(find_period(&x, &y, FloatExp::from(0.01), 2), None);
+    assert_eq!(find_period(&x, &y, FloatExp::from(0.01), 3), Some(3));
+    assert!(find_nucleus(&hp("-1.75"), &hp("0"), FloatExp::from(0.01), 2).is_none());
+    assert!(find_nucleus(&hp("-0.98"), &hp("0.01"), FloatExp::from(0.05), 1).is_none());
+}
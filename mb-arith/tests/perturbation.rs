@@ -0,0 +1,31 @@
+mod common;
+
+use mb_arith::*;
+use common::*;
+
+// the final zx*zx + zy*zy of the f64 deltas is within about 1e-9 of the HP kernel's on this view
+const PERTURBATION_TOLERANCE: f64 = 1.0e-6;
+
+#[test]
+fn perturbed_counts_match_hp() {
+    let view = View::new();
+    let orbit = view.reference_orbit();
+    let mut hp_data = HPData::new(view.chunks);
+    for offset in view.offsets() {
+        let expected = view.hp_smooth(&mut hp_data, offset);
+        let actual = count_iterations_perturbed_smooth(&orbit, offset.0.to_f64(), offset.1.to_f64(), MAX_ITERATIONS, DEFAULT_BAILOUT);
+        assert_counts_match(expected, actual, PERTURBATION_TOLERANCE);
+    }
+}
+
+#[test]
+fn perturbed_floatexp_counts_match_hp() {
+    let view = View::new();
+    let orbit = view.reference_orbit();
+    let mut hp_data = HPData::new(view.chunks);
+    for offset in view.offsets() {
+        let expected = view.hp_smooth(&mut hp_data, offset);
+        let actual = count_iterations_perturbed_smooth(&orbit, offset.0, offset.1, MAX_ITERATIONS, DEFAULT_BAILOUT);
+        assert_counts_match(expected, actual, PERTURBATION_TOLERANCE);
+    }
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use mb_arith::*;
+use common::*;
+
+// the series terms it leaves out are far below the rounding of the perturbation steps that follow it,
+// so the final zx*zx + zy*zy is as close to the HP kernel's as with perturbation alone; the counts are
+// exact on this view
+const SERIES_TOLERANCE: f64 = 1.0e-6;
+
+// the series has to cover the test pixel furthest from the center, 4 columns and 3 rows away
+fn series_radius(view: &View) -> FloatExp {
+    FloatExp::from(5.0*SPACING)*view.dx
+}
+
+#[test]
+fn series_skips_iterations() {
+    let view = View::new();
+    let orbit = view.reference_orbit();
+    let series = SeriesApproximation::new(&orbit, SERIES_TERMS, series_radius(&view), DEFAULT_BAILOUT);
+    // the pixels escape after about 5150 iterations, almost all of which the series covers
+    assert!(series.skipped() > 0, "the series skipped no iterations");
+}
+
+#[test]
+fn series_counts_match_hp() {
+    let view = View::new();
+    let orbit = view.reference_orbit();
+    let series = SeriesApproximation::new(&orbit, SERIES_TERMS, series_radius(&view), DEFAULT_BAILOUT);
+    let mut hp_data = HPData::new(view.chunks);
+    for offset in view.offsets() {
+        let expected = view.hp_smooth(&mut hp_data, offset);
+        let actual = count_iterations_series_smooth(&orbit, &series, offset.0.to_f64(), offset.1.to_f64(), MAX_ITERATIONS, DEFAULT_BAILOUT);
+        assert_counts_match(expected, actual, SERIES_TOLERANCE);
+    }
+}
+
+#[test]
+fn series_floatexp_counts_match_hp() {
+    let view = View::new();
+    let orbit = view.reference_orbit();
+    let series = SeriesApproximation::new(&orbit, SERIES_TERMS, series_radius(&view), DEFAULT_BAILOUT);
+    let mut hp_data = HPData::new(view.chunks);
+    for offset in view.offsets() {
+        let expected = view.hp_smooth(&mut hp_data, offset);
+        let actual = count_iterations_series_smooth(&orbit, &series, offset.0, offset.1, MAX_ITERATIONS, DEFAULT_BAILOUT);
+        assert_counts_match(expected, actual, SERIES_TOLERANCE);
+    }
+}
@@ -7,7 +7,7 @@
 use actix_rt::System;
 use actix_web::{web, App, HttpResponse, HttpServer, HttpRequest, Result};
 use actix_files::NamedFile;
-use serde::{Deserialize};
+use serde::{ Deserialize, Serialize };
 use std::path::PathBuf;
 use std::process::exit;
 
@@ -15,6 +15,11 @@ use std::env;
 static mut IMAGE_QUALITY: usize = 1;
 static mut NUM_THREADS: usize = 2;
 static mut U_TYPE: usize = 128;
+static mut PERTURBATION: bool = false;
+static mut SERIES_APPROXIMATION: bool = false;
+static mut FULL_WIDTH: bool = false;
+static mut BATCH: bool = false;
+static mut PERIODICITY: bool = true;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -32,9 +37,23 @@ Options:
                  defaults to 2
   -q, --quality  Set image quality from 2 (best) to 0 (worst); only affects high precision images;
                  lower quality may be faster in certain situations; defaults to 1
+  -p, --perturbation
+                 Use perturbation theory for all high precision images (much faster for deep zooms);
+                 otherwise only used when requested by the client
+  -s, --series-approximation
+                 Skip the early iterations of every perturbation image with series approximation;
+                 otherwise only used when requested by the client
+  --no-periodicity
+                 Don't check for periodic orbits, so points inside the set always run to max iterations;
+                 the images are the same either way
   --u32          Use 32 bit unsigned integers for high precision calculations (slowest)
   --u64          Use 64 bit unsigned integers for high precision calculations
-  --u128         Use 128 bit unsigned integers for high precision calculations (default)"#;
+  --u128         Use 128 bit unsigned integers for high precision calculations (default)
+  --u64-full     Use all 64 bits of 64 bit unsigned integers, with 128 bit products, for high precision
+                 z^2 + c (fastest); other high precision calculations use the --u32, --u64 or --u128
+                 integers, 128 bit if none is given
+  --batch        Iterate each row of high precision z^2 + c pixels in lockstep, so the arithmetic can be
+                 vectorized across pixels; can be faster with --u32 or --u64"#;
 
     let mut i = 1;
     while i < args.len() {
@@ -66,6 +85,15 @@ Options:
                     exit(1);
                 }
             }
+            "-p" | "--perturbation" => unsafe {
+                PERTURBATION = true;
+            }
+            "-s" | "--series-approximation" => unsafe {
+                SERIES_APPROXIMATION = true;
+            }
+            "--no-periodicity" => unsafe {
+                PERIODICITY = false;
+            }
             "--u32" => unsafe {
                 U_TYPE = 32;
             }
@@ -75,6 +103,12 @@ Options:
             "--u128" => unsafe {
                 U_TYPE = 128;
             }
+            "--u64-full" => unsafe {
+                FULL_WIDTH = true;
+            }
+            "--batch" => unsafe {
+                BATCH = true;
+            }
             "-h" | "--help" => {
                 println!("{help}");
                 exit(0);
@@ -86,10 +120,14 @@ Options:
         i += 1;
     }
 
-    println!("Mandelbrot server running on URL {url} with {} Rayon thread(s), image quality {}, and {} bit unsigned integers for high precision calculations.",
+    println!("Mandelbrot server running on URL {url} with {} Rayon thread(s), image quality {}, and {} bit unsigned integers for high precision calculations{}{}{}{}.",
         unsafe { NUM_THREADS },
         unsafe { 2 - IMAGE_QUALITY },
-        unsafe { U_TYPE },
+        if unsafe { FULL_WIDTH } { format!("full width 64 (otherwise {})", unsafe { U_TYPE }) } else { unsafe { U_TYPE }.to_string() },
+        if unsafe { PERTURBATION } { " using perturbation" } else { "" },
+        if unsafe { SERIES_APPROXIMATION } { " with series approximation" } else { "" },
+        if unsafe { PERIODICITY } { "" } else { ", without periodicity checking" },
+        if unsafe { BATCH } { ", batching the pixels of each row" } else { "" },
     );
     web_server(&url);
 }
@@ -113,6 +151,9 @@ fn web_server(url: &str) {
         App::new()
             .route("/mb-compute", web::post().to(compute_mandelbrot))
             .route("/mb-computeHP", web::post().to(compute_mandelbrot_hp))
+            .route("/mb-computeJulia", web::post().to(compute_julia))
+            .route("/mb-computeJuliaHP", web::post().to(compute_julia_hp))
+            .route("/mb-findNucleus", web::post().to(find_nucleus_xml))
             .route("/remoteCanComputeMB", web::get().to(ping))
             .route("/", web::get().to(redirect))
             .route("/{filename:.*}", web::get().to(file))
@@ -125,6 +166,174 @@ fn web_server(url: &str) {
 
 
 use mb_arith::*;
+use actix_web::http::header::{ HeaderName, HeaderValue };
+
+// *** responses *** //
+// what the kernels return for each pixel
+#[derive(Clone, Copy, Default)]
+pub struct Pixel {
+    count: i32,
+    // final zx*zx + zy*zy, for the smooth counts
+    norm: f64,
+    // distance to the set in pixels, if requested
+    distance: f64,
+    // final z, cycle period and interior distance, if requested
+    interior: Interior,
+    // closest approach to the orbit trap, if requested
+    trap: TrapHit,
+}
+
+impl From<(i32, f64)> for Pixel {
+    fn from((count, norm): (i32, f64)) -> Pixel {
+        Pixel { count, norm, ..Pixel::default() }
+    }
+}
+
+impl From<(i32, f64, f64)> for Pixel {
+    fn from((count, norm, distance): (i32, f64, f64)) -> Pixel {
+        Pixel { count, norm, distance, ..Pixel::default() }
+    }
+}
+
+impl From<(i32, f64, f64, Interior)> for Pixel {
+    fn from((count, norm, distance, interior): (i32, f64, f64, Interior)) -> Pixel {
+        Pixel { count, norm, distance, interior, ..Pixel::default() }
+    }
+}
+
+impl From<(i32, f64, TrapHit)> for Pixel {
+    fn from((count, norm, trap): (i32, f64, TrapHit)) -> Pixel {
+        Pixel { count, norm, trap, ..Pixel::default() }
+    }
+}
+
+#[derive(Serialize)]
+#[allow(non_snake_case)]
+struct MandelbrotResponse {
+    iterationCounts: Vec<Vec<i32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    smoothCounts: Option<Vec<Vec<f32>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distances: Option<Vec<Vec<f32>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finalZx: Option<Vec<Vec<f32>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finalZy: Option<Vec<Vec<f32>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    periods: Option<Vec<Vec<i32>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interiorDistances: Option<Vec<Vec<f32>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trapDistances: Option<Vec<Vec<f32>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trapIterations: Option<Vec<Vec<i32>>>,
+}
+
+fn pixel_channel(pixels: &[Vec<Pixel>], channel: impl Fn(&Pixel) -> f32) -> Vec<Vec<f32>> {
+    pixels.iter()
+        .map(| row | row.iter().map(&channel).collect())
+        .collect()
+}
+
+// the client gets just the iteration counts, unless it asked for more
+fn mandelbrot_response(pixels: Vec<Vec<Pixel>>, smooth: bool, distance: bool, interior: bool, trap: bool, bailout: f64, power: u32) -> HttpResponse {
+    let iteration_counts: Vec<Vec<i32>> = pixels.iter()
+        .map(| row | row.iter().map(| pixel | pixel.count).collect())
+        .collect();
+    if !smooth && !distance && !interior && !trap {
+        return HttpResponse::Ok().json(iteration_counts);
+    }
+
+    HttpResponse::Ok().json(MandelbrotResponse {
+        iterationCounts: iteration_counts,
+        smoothCounts: smooth.then(|| pixel_channel(&pixels, | pixel | smooth_iteration_count(pixel.count, pixel.norm, bailout, power) as f32)),
+        distances: distance.then(|| pixel_channel(&pixels, | pixel | pixel.distance as f32)),
+        finalZx: interior.then(|| pixel_channel(&pixels, | pixel | pixel.interior.zx as f32)),
+        finalZy: interior.then(|| pixel_channel(&pixels, | pixel | pixel.interior.zy as f32)),
+        periods: interior.then(|| pixels.iter()
+            .map(| row | row.iter().map(| pixel | pixel.interior.period).collect())
+            .collect()),
+        interiorDistances: interior.then(|| pixel_channel(&pixels, | pixel | pixel.interior.distance as f32)),
+        trapDistances: trap.then(|| pixel_channel(&pixels, | pixel | pixel.trap.distance as f32)),
+        trapIterations: trap.then(|| pixels.iter()
+            .map(| row | row.iter().map(| pixel | pixel.trap.iteration).collect())
+            .collect()),
+    })
+}
+
+// the precision the server chose, in an Mb-Precision header so the plain iteration count array can stay as it is
+fn with_precision(mut response: HttpResponse, precision: Precision) -> HttpResponse {
+    if let Ok(value) = HeaderValue::from_str(&precision.to_string()) {
+        response.headers_mut().insert(HeaderName::from_static("mb-precision"), value);
+    }
+    response
+}
+
+// how many iterations series approximation skipped for every pixel, in an Mb-Skipped-Iterations header
+fn with_skipped_iterations(mut response: HttpResponse, skipped: i32) -> HttpResponse {
+    response.headers_mut().insert(HeaderName::from_static("mb-skipped-iterations"), HeaderValue::from(skipped));
+    response
+}
+
+fn default_bailout() -> f64 {
+    DEFAULT_BAILOUT
+}
+
+fn default_power() -> u32 {
+    2
+}
+
+fn default_formula() -> String {
+    String::from("mandelbrot")
+}
+
+// the formula named in a request; only the Mandelbrot formula has other powers, and distance
+// estimates and interior data are only for z*z + c
+fn parse_formula(name: &str, power: u32, distance: bool, interior: bool) -> Result<FormulaKind, String> {
+    let formula = name.parse::<FormulaKind>().map_err(| e | e.to_string())?;
+    if formula != FormulaKind::Mandelbrot && power != 2 {
+        return Err(String::from("only the mandelbrot formula can have a power other than 2"));
+    }
+    if distance && (formula != FormulaKind::Mandelbrot || power != 2) {
+        return Err(String::from("distance estimates are only available for the mandelbrot formula with power 2"));
+    }
+    if interior && (formula != FormulaKind::Mandelbrot || power != 2) {
+        return Err(String::from("interior data is only available for the mandelbrot formula with power 2"));
+    }
+    Ok(formula)
+}
+
+// an orbit trap as given in a request
+#[derive(Deserialize)]
+struct TrapCoords {
+    // point, line or cross
+    shape: String,
+    #[serde(default)]
+    x: f64,
+    #[serde(default)]
+    y: f64,
+    // the line's angle from the real axis, in radians
+    #[serde(default)]
+    angle: f64,
+}
+
+// the orbit trap in a request, if any; traps are only for the quadratic formulas
+fn parse_trap(trap: &Option<TrapCoords>, power: u32) -> Result<Option<OrbitTrap>, String> {
+    let Some(trap) = trap else {
+        return Ok(None);
+    };
+    if power != 2 {
+        return Err(String::from("orbit traps are only available with power 2"));
+    }
+    let TrapCoords { x, y, angle, .. } = *trap;
+    match trap.shape.as_str() {
+        "point" => Ok(Some(OrbitTrap::Point { x, y })),
+        "line" => Ok(Some(OrbitTrap::Line { x, y, angle })),
+        "cross" => Ok(Some(OrbitTrap::Cross { x, y })),
+        shape => Err(format!("unknown orbit trap shape \"{shape}\"; expected point, line or cross")),
+    }
+}
+
 
 // *** low precision *** //
 #[derive(Deserialize)]
@@ -138,9 +347,76 @@ struct MandelbrotCoords {
     ymax: f64,
     dy: f64,
     maxIterations: i32,
+    // double-double precision: the low order parts of xmin, dx, ymax and dy
+    #[serde(default)]
+    doubleDouble: bool,
+    #[serde(default)]
+    xminLo: f64,
+    #[serde(default)]
+    dxLo: f64,
+    #[serde(default)]
+    ymaxLo: f64,
+    #[serde(default)]
+    dyLo: f64,
+    // also return fractional iteration counts
+    #[serde(default)]
+    smooth: bool,
+    // square of the bailout radius
+    #[serde(default = "default_bailout")]
+    bailout: f64,
+    // iterate z = z^power + c
+    #[serde(default = "default_power")]
+    power: u32,
+    // mandelbrot, burningShip, tricorn or celtic
+    #[serde(default = "default_formula")]
+    formula: String,
+    // also return distance estimates, in pixels
+    #[serde(default)]
+    distance: bool,
+    // also return the final z, attracting cycle period and interior distance in pixels
+    #[serde(default)]
+    interior: bool,
+    // also return the closest approach of each orbit to this trap, and its iteration
+    #[serde(default)]
+    trap: Option<TrapCoords>,
 }
 
 async fn compute_mandelbrot(mandelbrot_coords: web::Json<MandelbrotCoords>) -> HttpResponse {
+    let mut mandelbrot_coords = mandelbrot_coords.into_inner();
+    let bailout = mandelbrot_coords.bailout;
+    if !(bailout >= MIN_BAILOUT && bailout.is_finite()) {
+        return HttpResponse::BadRequest().body(format!("bailout must be at least {MIN_BAILOUT}"));
+    }
+    let power = mandelbrot_coords.power;
+    if !(2..=MAX_POWER).contains(&power) {
+        return HttpResponse::BadRequest().body(format!("power must be between 2 and {MAX_POWER}"));
+    }
+    let formula = match parse_formula(&mandelbrot_coords.formula, power, mandelbrot_coords.distance, mandelbrot_coords.interior) {
+        Ok(formula) => formula,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    let trap = match parse_trap(&mandelbrot_coords.trap, power) {
+        Ok(trap) => trap,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+
+    // double-double isn't needed if f64 can tell the pixels apart; the request's f64 values can't be made any more
+    // precise though, so f64 requests are done in f64 even if the view needs more
+    let xmax = mandelbrot_coords.xmin + mandelbrot_coords.columns as f64*mandelbrot_coords.dx;
+    let ymin = mandelbrot_coords.ymax - (mandelbrot_coords.firstRow + mandelbrot_coords.rows) as f64*mandelbrot_coords.dy;
+    let extent = mandelbrot_coords.xmin.abs().max(xmax.abs()).max(mandelbrot_coords.ymax.abs()).max(ymin.abs());
+    if choose_precision(extent, FloatExp::from(mandelbrot_coords.dx.min(mandelbrot_coords.dy))) == Precision::F64 {
+        mandelbrot_coords.doubleDouble = false;
+    }
+    let precision = if mandelbrot_coords.doubleDouble { Precision::DoubleDouble } else { Precision::F64 };
+
+    let pixels = compute_mandelbrot_pixels(&mandelbrot_coords, formula, trap);
+    let response = mandelbrot_response(pixels, mandelbrot_coords.smooth, mandelbrot_coords.distance, mandelbrot_coords.interior, trap.is_some(), bailout, power);
+    with_precision(response, precision)
+}
+
+// compute the request in f64, or in double-double if it has the low order parts
+fn compute_mandelbrot_pixels(mandelbrot_coords: &MandelbrotCoords, formula: FormulaKind, trap: Option<OrbitTrap>) -> Vec<Vec<Pixel>> {
     let xmin = mandelbrot_coords.xmin;
     let dx = mandelbrot_coords.dx;
     let columns = mandelbrot_coords.columns;
@@ -149,16 +425,76 @@ async fn compute_mandelbrot(mandelbrot_coords: web::Json<MandelbrotCoords>) -> H
     let first_row = mandelbrot_coords.firstRow;
     let rows = mandelbrot_coords.rows;
     let max_iterations = mandelbrot_coords.maxIterations;
-
-    let mut iteration_counts = vec![vec![0; columns]; rows];
-    for i in 0..rows {
-        let y = ymax - (first_row + i) as f64*dy;
-        for j in 0..columns {
-            iteration_counts[i][j] = count_iterations(xmin + j as f64*dx, y, max_iterations);
+    let bailout = mandelbrot_coords.bailout;
+    let power = mandelbrot_coords.power;
+    let distance = mandelbrot_coords.distance;
+    let interior = mandelbrot_coords.interior;
+    let periodicity = unsafe { PERIODICITY };
+    let mut pixels = vec![vec![Pixel::default(); columns]; rows];
+    if mandelbrot_coords.doubleDouble {
+        let xmin = DoubleDouble::new(xmin, mandelbrot_coords.xminLo);
+        let dx = DoubleDouble::new(dx, mandelbrot_coords.dxLo);
+        let ymax = DoubleDouble::new(ymax, mandelbrot_coords.ymaxLo);
+        let dy = DoubleDouble::new(dy, mandelbrot_coords.dyLo);
+        for (i, row) in pixels.iter_mut().enumerate() {
+            let y = ymax - DoubleDouble::from((first_row + i) as f64)*dy;
+            for (j, pixel) in row.iter_mut().enumerate() {
+                let x = xmin + DoubleDouble::from(j as f64)*dx;
+                // the interior kernel also estimates the exterior distance
+                *pixel = if interior {
+                    count_iterations_dd_interior(x, y, dx.hi(), max_iterations, bailout).into()
+                } else if distance {
+                    count_iterations_dd_distance(x, y, dx.hi(), max_iterations, bailout, periodicity).into()
+                } else if let Some(trap) = &trap {
+                    formula.count_iterations_dd_trap(x, y, trap, max_iterations, bailout, periodicity).into()
+                } else if power == 2 {
+                    formula.count_iterations_dd_smooth(x, y, max_iterations, bailout, periodicity).into()
+                } else {
+                    count_iterations_dd_multibrot_smooth(x, y, max_iterations, bailout, power, periodicity).into()
+                };
+                // the distance and interior kernels don't test the trap
+                if interior || distance {
+                    if let Some(trap) = &trap {
+                        pixel.trap = formula.count_iterations_dd_trap(x, y, trap, max_iterations, bailout, periodicity).2;
+                    }
+                }
+            }
+        }
+    } else if power == 2 && formula == FormulaKind::Mandelbrot && !distance && !interior && trap.is_none() {
+        // plain Mandelbrot images go to the multi-pixel kernel
+        for (i, row) in pixels.iter_mut().enumerate() {
+            let y = ymax - (first_row + i) as f64*dy;
+            for (pixel, result) in row.iter_mut().zip(count_iterations_smooth_row(xmin, dx, columns, y, max_iterations, bailout, periodicity)) {
+                *pixel = result.into();
+            }
+        }
+    } else {
+        for (i, row) in pixels.iter_mut().enumerate() {
+            let y = ymax - (first_row + i) as f64*dy;
+            for (j, pixel) in row.iter_mut().enumerate() {
+                let x = xmin + j as f64*dx;
+                *pixel = if interior {
+                    count_iterations_interior(x, y, dx, max_iterations, bailout).into()
+                } else if distance {
+                    count_iterations_distance(x, y, dx, max_iterations, bailout, periodicity).into()
+                } else if let Some(trap) = &trap {
+                    formula.count_iterations_trap(x, y, trap, max_iterations, bailout, periodicity).into()
+                } else if power == 2 {
+                    formula.count_iterations_smooth(x, y, max_iterations, bailout, periodicity).into()
+                } else {
+                    count_iterations_multibrot_smooth(x, y, max_iterations, bailout, power, periodicity).into()
+                };
+                // the distance and interior kernels don't test the trap
+                if interior || distance {
+                    if let Some(trap) = &trap {
+                        pixel.trap = formula.count_iterations_trap(x, y, trap, max_iterations, bailout, periodicity).2;
+                    }
+                }
+            }
         }
     }
 
-    HttpResponse::Ok().json(iteration_counts)
+    pixels
 }
 
 
@@ -174,6 +510,35 @@ struct MandelbrotCoordsHP {
     ymax: Vec<u32>,
     dy: Vec<u32>,
     maxIterations: i32,
+    #[serde(default)]
+    perturbation: bool,
+    // skip the early perturbation iterations with series approximation
+    #[serde(default)]
+    seriesApproximation: bool,
+    // skip runs of perturbation iterations with a bilinear approximation table, in place of seriesApproximation
+    #[serde(default)]
+    bla: bool,
+    // also return fractional iteration counts
+    #[serde(default)]
+    smooth: bool,
+    // square of the bailout radius
+    #[serde(default = "default_bailout")]
+    bailout: f64,
+    // iterate z = z^power + c
+    #[serde(default = "default_power")]
+    power: u32,
+    // mandelbrot, burningShip, tricorn or celtic
+    #[serde(default = "default_formula")]
+    formula: String,
+    // also return distance estimates, in pixels
+    #[serde(default)]
+    distance: bool,
+    // also return the final z, attracting cycle period and interior distance in pixels
+    #[serde(default)]
+    interior: bool,
+    // also return the closest approach of each orbit to this trap, and its iteration
+    #[serde(default)]
+    trap: Option<TrapCoords>,
 }
 
 /*
@@ -197,67 +562,233 @@ exports.computeMandelbrotHP = function(mandelbrotCoords) {
 };
 */
 async fn compute_mandelbrot_hp(mandelbrot_coords_hp: web::Json<MandelbrotCoordsHP>) -> HttpResponse {
+    if let Err(message) = check_hp_view(&mandelbrot_coords_hp.xmin, &mandelbrot_coords_hp.dx, &mandelbrot_coords_hp.ymax, &mandelbrot_coords_hp.dy) {
+        return HttpResponse::BadRequest().body(message);
+    }
+    let power = mandelbrot_coords_hp.power;
+    if !(2..=MAX_POWER).contains(&power) {
+        return HttpResponse::BadRequest().body(format!("power must be between 2 and {MAX_POWER}"));
+    }
+    let formula = match parse_formula(&mandelbrot_coords_hp.formula, power, mandelbrot_coords_hp.distance, mandelbrot_coords_hp.interior) {
+        Ok(formula) => formula,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    let trap = match parse_trap(&mandelbrot_coords_hp.trap, power) {
+        Ok(trap) => trap,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
 
-    // ignoring the last u32 chunk seems to be a small speed optimization which reduces precision but doesn't affect image quality
-    let u32_chunks = mandelbrot_coords_hp.xmin.len() - unsafe { IMAGE_QUALITY };
+    // use wider chunks if the integer part is too small for the bailout
+    let bailout = mandelbrot_coords_hp.bailout;
+    if !(MIN_BAILOUT..=max_bailout_hp::<u128>(power)).contains(&bailout) {
+        return HttpResponse::BadRequest().body(format!("bailout must be between {MIN_BAILOUT} and {} for power {power}", max_bailout_hp::<u128>(power)));
+    }
 
-    let iteration_counts = match unsafe { U_TYPE } {
-        32 => {
-            let xmin = u32_to_t::<u32>(&mandelbrot_coords_hp.xmin);
-            let dx = u32_to_t::<u32>(&mandelbrot_coords_hp.dx);
-            let yval = u32_to_t::<u32>(&mandelbrot_coords_hp.ymax);
-            let dy = u32_to_t::<u32>(&mandelbrot_coords_hp.dy);
-            compute_mandelbrot_hp_t(&xmin, &dx, &yval, &dy, mandelbrot_coords_hp.rows, mandelbrot_coords_hp.columns, mandelbrot_coords_hp.maxIterations, u32_chunks, unsafe { NUM_THREADS })
-        }
-        64 => {
-            let xmin = u32_to_t::<u64>(&mandelbrot_coords_hp.xmin);
-            let dx = u32_to_t::<u64>(&mandelbrot_coords_hp.dx);
-            let yval = u32_to_t::<u64>(&mandelbrot_coords_hp.ymax);
-            let dy = u32_to_t::<u64>(&mandelbrot_coords_hp.dy);
-            compute_mandelbrot_hp_t(&xmin, &dx, &yval, &dy, mandelbrot_coords_hp.rows, mandelbrot_coords_hp.columns, mandelbrot_coords_hp.maxIterations, u32_chunks, unsafe { NUM_THREADS })
-        }
-        128 => {
-            let xmin = u32_to_t::<u128>(&mandelbrot_coords_hp.xmin);
-            let dx = u32_to_t::<u128>(&mandelbrot_coords_hp.dx);
-            let yval = u32_to_t::<u128>(&mandelbrot_coords_hp.ymax);
-            let dy = u32_to_t::<u128>(&mandelbrot_coords_hp.dy);
-            compute_mandelbrot_hp_t(&xmin, &dx, &yval, &dy, mandelbrot_coords_hp.rows, mandelbrot_coords_hp.columns, mandelbrot_coords_hp.maxIterations, u32_chunks, unsafe { NUM_THREADS })
-        }
-        _ => panic!("illegal size!")
+    let precision = match choose_hp_precision(&mandelbrot_coords_hp.xmin, &mandelbrot_coords_hp.dx, &mandelbrot_coords_hp.ymax, &mandelbrot_coords_hp.dy,
+        mandelbrot_coords_hp.columns, mandelbrot_coords_hp.rows) {
+        Precision::HP { u32_chunks } => Precision::HP { u32_chunks: hp_u32_chunks(u32_chunks, mandelbrot_coords_hp.xmin.len()) },
+        precision => precision,
+    };
+    // the full width kernel only handles z*z + c; perturbation is faster when it's asked for
+    // everything else falls back to the U_TYPE chunks, --u128 unless --u32 or --u64 was given
+    let full_width = unsafe { FULL_WIDTH } && power == 2 && formula == FormulaKind::Mandelbrot && !mandelbrot_coords_hp.distance
+        && !mandelbrot_coords_hp.interior && trap.is_none() && !(mandelbrot_coords_hp.perturbation || unsafe { PERTURBATION });
+    let (pixels, skipped) = match precision {
+        Precision::HP { u32_chunks } if full_width => (compute_mandelbrot_full(&mandelbrot_coords_hp, u32_chunks, unsafe { NUM_THREADS }), None),
+        Precision::HP { u32_chunks } => match u_type_for_bailout(bailout, power) {
+            32 => compute_mandelbrot_hp_u::<u32>(&mandelbrot_coords_hp, formula, trap, u32_chunks),
+            64 => compute_mandelbrot_hp_u::<u64>(&mandelbrot_coords_hp, formula, trap, u32_chunks),
+            128 => compute_mandelbrot_hp_u::<u128>(&mandelbrot_coords_hp, formula, trap, u32_chunks),
+            _ => panic!("illegal size!")
+        },
+        _ => (compute_mandelbrot_pixels(&low_precision_coords(&mandelbrot_coords_hp, precision), formula, trap), None),
     };
-    HttpResponse::Ok().json(iteration_counts)
+    let response = mandelbrot_response(pixels, mandelbrot_coords_hp.smooth, mandelbrot_coords_hp.distance, mandelbrot_coords_hp.interior, trap.is_some(), bailout, power);
+    match skipped {
+        Some(skipped) => with_skipped_iterations(with_precision(response, precision), skipped),
+        None => with_precision(response, precision),
+    }
+}
+
+// xmin, dx, ymax and dy of an HP request are u32 chunks, the same number of them, and with at least one
+// fraction chunk
+fn check_hp_view(xmin: &[u32], dx: &[u32], ymax: &[u32], dy: &[u32]) -> Result<(), String> {
+    if xmin.len() < 2 || [dx, ymax, dy].iter().any(| a | a.len() != xmin.len()) {
+        return Err(String::from("xmin, dx, ymax and dy must have the same number of chunks, at least 2"));
+    }
+    Ok(())
+}
+
+// the u32 chunks to compute an HP request with, given the precision it needs and the len chunks it was sent with;
+// ignoring the last u32 chunk seems to be a small speed optimization which reduces precision but doesn't affect
+// image quality.  At least one fraction chunk is kept
+fn hp_u32_chunks(u32_chunks: usize, len: usize) -> usize {
+    u32_chunks.min(len.saturating_sub(unsafe { IMAGE_QUALITY })).max(2)
+}
+
+// the cheapest precision that can tell the request's pixels apart, from its view in u32 chunks
+fn choose_hp_precision(xmin: &[u32], dx: &[u32], ymax: &[u32], dy: &[u32], columns: usize, rows: usize) -> Precision {
+    let xmin = HpFixed::<u64>::from_u32_chunks(xmin);
+    let dx = HpFixed::<u64>::from_u32_chunks(dx);
+    let ymax = HpFixed::<u64>::from_u32_chunks(ymax);
+    let dy = HpFixed::<u64>::from_u32_chunks(dy);
+    let xmax = xmin.to_f64() + columns as f64*dx.to_f64();
+    let ymin = ymax.to_f64() - rows as f64*dy.to_f64();
+    let extent = xmin.to_f64().abs().max(xmax.abs()).max(ymax.to_f64().abs()).max(ymin.abs());
+    let (dx, dy) = (dx.to_floatexp(), dy.to_floatexp());
+    choose_precision(extent, if dx < dy { dx } else { dy })
+}
+
+// the request for the f64 or double-double kernels
+fn low_precision_coords(mandelbrot_coords_hp: &MandelbrotCoordsHP, precision: Precision) -> MandelbrotCoords {
+    let to_dd = | a: &[u32] | HpFixed::<u64>::from_u32_chunks(a).to_double_double();
+    let (xmin, dx) = (to_dd(&mandelbrot_coords_hp.xmin), to_dd(&mandelbrot_coords_hp.dx));
+    let (ymax, dy) = (to_dd(&mandelbrot_coords_hp.ymax), to_dd(&mandelbrot_coords_hp.dy));
+    MandelbrotCoords {
+        columns: mandelbrot_coords_hp.columns,
+        firstRow: 0,
+        rows: mandelbrot_coords_hp.rows,
+        xmin: xmin.hi(),
+        dx: dx.hi(),
+        ymax: ymax.hi(),
+        dy: dy.hi(),
+        maxIterations: mandelbrot_coords_hp.maxIterations,
+        doubleDouble: precision == Precision::DoubleDouble,
+        xminLo: xmin.lo(),
+        dxLo: dx.lo(),
+        ymaxLo: ymax.lo(),
+        dyLo: dy.lo(),
+        smooth: mandelbrot_coords_hp.smooth,
+        bailout: mandelbrot_coords_hp.bailout,
+        power: mandelbrot_coords_hp.power,
+        formula: mandelbrot_coords_hp.formula.clone(),
+        distance: mandelbrot_coords_hp.distance,
+        interior: mandelbrot_coords_hp.interior,
+        // already parsed
+        trap: None,
+    }
+}
+
+// the configured chunk size, or a wider one if its integer part is too small for the bailout
+fn u_type_for_bailout(bailout: f64, power: u32) -> usize {
+    let mut u_type = unsafe { U_TYPE };
+    if u_type == 32 && bailout > max_bailout_hp::<u32>(power) {
+        u_type = 64;
+    }
+    if u_type == 64 && bailout > max_bailout_hp::<u64>(power) {
+        u_type = 128;
+    }
+    u_type
 }
 
-use std::ops::{ BitAnd, BitAndAssign, Shl, Shr, AddAssign, Sub, Mul };
-use num::traits::{ Zero, One, AsPrimitive };
-use core::cmp::PartialEq;
 use core::mem::size_of;
 use rayon::prelude::*;
 
-pub fn compute_mandelbrot_hp_t<T>(xmin: &[T], dx: &[T], yval: &[T], dy: &[T], rows: usize, columns: usize, max_iter: i32, u32_chunks: usize, num_threads: usize) -> Vec<Vec<i32>>
-where T: Sync + Zero + Copy,
-    // add, sq, multiply, negate, incr, count_iterations requirements
-    T: One + AddAssign + BitAndAssign + Sub<Output = T> + PartialEq +
-        BitAnd + Shr<usize, Output = T> + Shl<usize, Output = T> + Copy + 'static,
-    <T as BitAnd>::Output: PartialEq<T>,
-    u64: AsPrimitive<T>,
-    T: std::fmt::LowerHex,
+// convert the request to T chunks and compute it with the high precision or perturbation kernel,
+// also returning the iterations skipped by series approximation, if it was used
+fn compute_mandelbrot_hp_u<T>(mandelbrot_coords_hp: &MandelbrotCoordsHP, formula: FormulaKind, trap: Option<OrbitTrap>, u32_chunks: usize) -> (Vec<Vec<Pixel>>, Option<i32>)
+where T: Limb,
 {
-    // chunks: 1 for the integral part, plus however many T elements are needed for the fractional part
-    let chunks = 1 + {
-        let t_to_u32_size_ratio = size_of::<T>()/size_of::<u32>();
-        (u32_chunks - 1 + t_to_u32_size_ratio - 1)/t_to_u32_size_ratio
-    };
+    let xmin = u32_to_t::<T>(&mandelbrot_coords_hp.xmin);
+    let dx = u32_to_t::<T>(&mandelbrot_coords_hp.dx);
+    let yval = u32_to_t::<T>(&mandelbrot_coords_hp.ymax);
+    let dy = u32_to_t::<T>(&mandelbrot_coords_hp.dy);
+    // perturbation only handles z*z + c, and doesn't find interior cycles or test orbit traps
+    if (mandelbrot_coords_hp.perturbation || unsafe { PERTURBATION }) && mandelbrot_coords_hp.power == 2 && formula == FormulaKind::Mandelbrot
+        && !mandelbrot_coords_hp.interior && trap.is_none() {
+        let series = mandelbrot_coords_hp.seriesApproximation || unsafe { SERIES_APPROXIMATION };
+        compute_mandelbrot_perturbed_t(&xmin, &dx, &yval, &dy, mandelbrot_coords_hp.rows, mandelbrot_coords_hp.columns, mandelbrot_coords_hp.maxIterations, mandelbrot_coords_hp.bailout, mandelbrot_coords_hp.distance, series, mandelbrot_coords_hp.bla, u32_chunks, unsafe { NUM_THREADS })
+    } else {
+        (compute_mandelbrot_hp_t(&xmin, &dx, &yval, &dy, mandelbrot_coords_hp.rows, mandelbrot_coords_hp.columns, mandelbrot_coords_hp.maxIterations, mandelbrot_coords_hp.bailout, mandelbrot_coords_hp.power, formula, mandelbrot_coords_hp.distance, mandelbrot_coords_hp.interior, trap, u32_chunks, unsafe { NUM_THREADS }), None)
+    }
+}
+
+// chunks: 1 for the integral part, plus however many T elements are needed for the fractional part
+fn t_chunks<T>(u32_chunks: usize) -> usize {
+    let t_to_u32_size_ratio = size_of::<T>()/size_of::<u32>();
+    1 + (u32_chunks - 1).div_ceil(t_to_u32_size_ratio)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn compute_mandelbrot_hp_t<T>(xmin: &[T], dx: &[T], yval: &[T], dy: &[T], rows: usize, columns: usize, max_iter: i32, bailout: f64, power: u32, formula: FormulaKind, distance: bool, interior: bool, trap: Option<OrbitTrap>, u32_chunks: usize, num_threads: usize) -> Vec<Vec<Pixel>>
+where T: Limb,
+{
+    let chunks = t_chunks::<T>(u32_chunks);
     // println!("{} {} {}", u32_chunks - 1 + unsafe { IMAGE_QUALITY }, u32_chunks - 1, chunks - 1 );
 
-    let mut dy_neg = vec![T::zero(); xmin.len()];
-    negate(&dy, &mut dy_neg);
-    let mut y_vals = vec![vec![T::zero(); xmin.len()]; rows];
+    if power == 2 && formula == FormulaKind::Mandelbrot && !distance && !interior && trap.is_none() && unsafe { BATCH } {
+        return compute_mandelbrot_hp_batch(xmin, dx, yval, dy, rows, columns, max_iter, bailout, chunks, num_threads);
+    }
+    if power == 2 && formula == FormulaKind::Mandelbrot && !distance && !interior && trap.is_none() {
+        if let Some(pixels) = compute_mandelbrot_hp_fixed(xmin, dx, yval, dy, rows, columns, max_iter, bailout, chunks, num_threads) {
+            return pixels;
+        }
+    }
+
+    let pixel_size = t_to_floatexp(dx);
+    compute_hp_rows(xmin, dx, yval, dy, rows, columns, chunks, num_threads, | hp_data, x, y | {
+        // the interior kernel also estimates the exterior distance
+        let mut pixel: Pixel = if interior {
+            count_iterations_hp_interior(hp_data, x, y, pixel_size, max_iter, bailout).into()
+        } else if distance {
+            count_iterations_hp_distance(hp_data, x, y, pixel_size, max_iter, bailout).into()
+        } else if let Some(trap) = &trap {
+            formula.count_iterations_hp_trap(hp_data, x, y, trap, max_iter, bailout).into()
+        } else if power == 2 {
+            formula.count_iterations_hp_smooth(hp_data, x, y, max_iter, bailout).into()
+        } else {
+            count_iterations_hp_multibrot_smooth(hp_data, x, y, max_iter, bailout, power).into()
+        };
+        // the distance and interior kernels don't test the trap
+        if interior || distance {
+            if let Some(trap) = &trap {
+                pixel.trap = formula.count_iterations_hp_trap(hp_data, x, y, trap, max_iter, bailout).2;
+            }
+        }
+        pixel
+    })
+}
+
+// the fixed size kernel for chunks, if it's one of the chunk counts it's compiled for
+#[allow(clippy::too_many_arguments)]
+fn compute_mandelbrot_hp_fixed<T>(xmin: &[T], dx: &[T], yval: &[T], dy: &[T], rows: usize, columns: usize, max_iter: i32, bailout: f64, chunks: usize, num_threads: usize) -> Option<Vec<Vec<Pixel>>>
+where T: Limb,
+{
+    let periodicity = unsafe { PERIODICITY };
+    macro_rules! dispatch {
+        ($($n:literal),*) => {
+            match chunks {
+                $($n => Some(compute_hp_rows(xmin, dx, yval, dy, rows, columns, chunks, num_threads, | _, x, y |
+                    count_iterations_hp_smooth_n::<T, $n>(x.try_into().unwrap(), y.try_into().unwrap(), max_iter, bailout, periodicity).into())),)*
+                _ => None,
+            }
+        }
+    }
+    dispatch!(2, 3, 4, 5, 6, 7, 8)
+}
+
+// the y value of each row
+fn hp_row_ys<T>(yval: &[T], dy: &[T], rows: usize) -> Vec<Vec<T>>
+where T: Limb,
+{
+    let mut dy_neg = vec![T::zero(); yval.len()];
+    negate(dy, &mut dy_neg);
+    let mut y_vals = vec![vec![T::zero(); yval.len()]; rows];
     y_vals[0] = yval.to_vec();
     for i in 1..rows {
-        (0..xmin.len()).for_each(| j | y_vals[i][j] = y_vals[i - 1][j]);
+        (0..yval.len()).for_each(| j | y_vals[i][j] = y_vals[i - 1][j]);
         incr(&mut y_vals[i], &dy_neg);
     }
+    y_vals
+}
+
+// compute the pixels a slice of rows per thread, calling kernel with the high precision x and y of each
+#[allow(clippy::too_many_arguments)]
+fn compute_hp_rows<T, F>(xmin: &[T], dx: &[T], yval: &[T], dy: &[T], rows: usize, columns: usize, chunks: usize, num_threads: usize, kernel: F) -> Vec<Vec<Pixel>>
+where F: Fn(&mut HPData<T>, &[T], &[T]) -> Pixel + Sync,
+    T: Limb,
+{
+    let y_vals = hp_row_ys(yval, dy, rows);
 
     let slice_size = core::cmp::max(1, rows/num_threads);
     y_vals
@@ -265,17 +796,469 @@ where T: Sync + Zero + Copy,
         .map(| y_vals | {
             let mut x_val = xmin.to_vec();
             let rows = y_vals.len();
-            let mut hp_data = HPData::new(chunks);
-            let mut iteration_counts = vec![vec![0; columns]; rows];
+            let mut hp_data = HPData::with_periodicity(chunks, unsafe { PERIODICITY });
+            let mut pixels = vec![vec![Pixel::default(); columns]; rows];
             for i in 0..rows {
-                for j in 0..columns {
-                    iteration_counts[i][j] = count_iterations_hp(&mut hp_data, &x_val[0..chunks], &y_vals[i][0..chunks], max_iter);
-                    incr(&mut x_val, &dx);
+                for pixel in pixels[i].iter_mut() {
+                    *pixel = kernel(&mut hp_data, &x_val[0..chunks], &y_vals[i][0..chunks]);
+                    incr(&mut x_val, dx);
+                }
+                x_val.copy_from_slice(xmin);
+            }
+            pixels
+        })
+        .flatten()
+        .collect()
+}
+
+// same as compute_hp_rows with count_iterations_hp_smooth, iterating the pixels of each row as a batch
+#[allow(clippy::too_many_arguments)]
+fn compute_mandelbrot_hp_batch<T>(xmin: &[T], dx: &[T], yval: &[T], dy: &[T], rows: usize, columns: usize, max_iter: i32, bailout: f64, chunks: usize, num_threads: usize) -> Vec<Vec<Pixel>>
+where T: Limb,
+{
+    let y_vals = hp_row_ys(yval, dy, rows);
+
+    let slice_size = core::cmp::max(1, rows/num_threads);
+    y_vals
+        .par_chunks(slice_size)
+        .map(| y_vals | {
+            let mut x_val = xmin.to_vec();
+            let mut batch = HPBatch::with_periodicity(chunks, columns, unsafe { PERIODICITY });
+            y_vals.iter().map(| y_val | {
+                batch.clear();
+                for _ in 0..columns {
+                    batch.push(&x_val[0..chunks], &y_val[0..chunks]);
+                    incr(&mut x_val, dx);
                 }
                 x_val.copy_from_slice(xmin);
+                count_iterations_hp_smooth_batch(&mut batch, max_iter, bailout).iter().map(| &result | result.into()).collect()
+            }).collect::<Vec<Vec<Pixel>>>()
+        })
+        .flatten()
+        .collect()
+}
+
+// same as compute_hp_rows with count_iterations_hp_smooth, using full width u64 chunks
+fn compute_mandelbrot_full(mandelbrot_coords_hp: &MandelbrotCoordsHP, u32_chunks: usize, num_threads: usize) -> Vec<Vec<Pixel>> {
+    let xmin = u32_to_full(&mandelbrot_coords_hp.xmin);
+    let dx = u32_to_full(&mandelbrot_coords_hp.dx);
+    let yval = u32_to_full(&mandelbrot_coords_hp.ymax);
+    let dy = u32_to_full(&mandelbrot_coords_hp.dy);
+    let (rows, columns) = (mandelbrot_coords_hp.rows, mandelbrot_coords_hp.columns);
+    let (max_iter, bailout) = (mandelbrot_coords_hp.maxIterations, mandelbrot_coords_hp.bailout);
+    let chunks = full_chunks(u32_chunks);
+
+    let mut dy_neg = vec![0; xmin.len()];
+    negate_full(&dy, &mut dy_neg);
+    let mut y_vals = vec![yval; rows];
+    for i in 1..rows {
+        y_vals[i] = y_vals[i - 1].clone();
+        incr_full(&mut y_vals[i], &dy_neg);
+    }
+
+    let slice_size = core::cmp::max(1, rows/num_threads);
+    y_vals
+        .par_chunks(slice_size)
+        .map(| y_vals | {
+            let mut x_val = xmin.clone();
+            let rows = y_vals.len();
+            let mut hp_data = HPData::<u64>::with_periodicity(chunks, unsafe { PERIODICITY });
+            let mut pixels = vec![vec![Pixel::default(); columns]; rows];
+            for i in 0..rows {
+                for pixel in pixels[i].iter_mut() {
+                    *pixel = count_iterations_full_smooth(&mut hp_data, &x_val[0..chunks], &y_vals[i][0..chunks], max_iter, bailout).into();
+                    incr_full(&mut x_val, &dx);
+                }
+                x_val.copy_from_slice(&xmin);
             }
-            iteration_counts
+            pixels
+        })
+        .flatten()
+        .collect()
+}
+
+// iterate each pixel as an f64 offset from a high precision reference orbit at the center of the tile
+#[allow(clippy::too_many_arguments)]
+pub fn compute_mandelbrot_perturbed_t<T>(xmin: &[T], dx: &[T], yval: &[T], dy: &[T], rows: usize, columns: usize, max_iter: i32, bailout: f64, distance: bool, series: bool, bla: bool, u32_chunks: usize, num_threads: usize) -> (Vec<Vec<Pixel>>, Option<i32>)
+where T: Limb,
+{
+    let chunks = t_chunks::<T>(u32_chunks);
+
+    let (ref_row, ref_column) = (rows/2, columns/2);
+    let mut dy_neg = vec![T::zero(); xmin.len()];
+    negate(dy, &mut dy_neg);
+    let mut x_ref = xmin.to_vec();
+    (0..ref_column).for_each(| _ | incr(&mut x_ref, dx));
+    let mut y_ref = yval.to_vec();
+    (0..ref_row).for_each(| _ | incr(&mut y_ref, &dy_neg));
+    let orbit = ReferenceOrbit::new(&mut HPData::new(chunks), &x_ref[0..chunks], &y_ref[0..chunks], max_iter, bailout);
+
+    let dx = t_to_floatexp(dx);
+    let dy = t_to_floatexp(dy);
+
+    // the series and the table have to hold out to the corner farthest from the reference point
+    let (dcx, dcy) = (FloatExp::from(ref_column.max(columns.saturating_sub(ref_column + 1)) as f64)*dx, FloatExp::from(ref_row.max(rows.saturating_sub(ref_row + 1)) as f64)*dy);
+    let radius = (dcx.sq() + dcy.sq()).sqrt();
+    let series = (series && !bla).then(|| SeriesApproximation::new(&orbit, SERIES_TERMS, radius, bailout));
+    let pixels = if dx.exponent() < F64_DELTA_MIN_EXPONENT || dy.exponent() < F64_DELTA_MIN_EXPONENT {
+        let table = bla.then(|| BlaTable::new(&orbit, radius));
+        compute_perturbed_rows(&orbit, series.as_ref(), table.as_ref(), dx, dy, ref_row, ref_column, rows, columns, max_iter, bailout, distance.then_some(dx), num_threads)
+    } else {
+        let table = bla.then(|| BlaTable::new(&orbit, radius));
+        compute_perturbed_rows(&orbit, series.as_ref(), table.as_ref(), dx.to_f64(), dy.to_f64(), ref_row, ref_column, rows, columns, max_iter, bailout, distance.then_some(dx), num_threads)
+    };
+    (pixels, series.map(| series | series.skipped()))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn compute_perturbed_rows<D>(orbit: &ReferenceOrbit, series: Option<&SeriesApproximation>, table: Option<&BlaTable<D>>, dx: D, dy: D, ref_row: usize, ref_column: usize, rows: usize, columns: usize, max_iter: i32, bailout: f64, pixel_size: Option<FloatExp>, num_threads: usize) -> Vec<Vec<Pixel>>
+where D: Delta + Send + Sync,
+{
+    let row_indexes: Vec<usize> = (0..rows).collect();
+    let slice_size = core::cmp::max(1, rows/num_threads);
+    row_indexes
+        .par_chunks(slice_size)
+        .map(| row_indexes | {
+            row_indexes.iter().map(| &i | {
+                let dcy = D::from_f64(ref_row as f64 - i as f64)*dy;
+                (0..columns).map(| j | {
+                    let dcx = D::from_f64(j as f64 - ref_column as f64)*dx;
+                    if let Some(table) = table {
+                        return match pixel_size {
+                            Some(pixel_size) => count_iterations_bla_distance(orbit, table, dcx, dcy, pixel_size, max_iter, bailout).into(),
+                            None => count_iterations_bla_smooth(orbit, table, dcx, dcy, max_iter, bailout).into(),
+                        };
+                    }
+                    match (pixel_size, series) {
+                        (Some(pixel_size), Some(series)) => count_iterations_series_distance(orbit, series, dcx, dcy, pixel_size, max_iter, bailout).into(),
+                        (Some(pixel_size), None) => count_iterations_perturbed_distance(orbit, dcx, dcy, pixel_size, max_iter, bailout).into(),
+                        (None, Some(series)) => count_iterations_series_smooth(orbit, series, dcx, dcy, max_iter, bailout).into(),
+                        (None, None) => count_iterations_perturbed_smooth(orbit, dcx, dcy, max_iter, bailout).into(),
+                    }
+                }).collect::<Vec<Pixel>>()
+            }).collect::<Vec<Vec<Pixel>>>()
         })
         .flatten()
         .collect()
 }
+
+
+// *** Julia sets *** //
+// same as MandelbrotCoords, plus the fixed c = cx + cy*i
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct JuliaCoords {
+    columns: usize,
+    firstRow: usize,
+    rows: usize,
+    xmin: f64,
+    dx: f64,
+    ymax: f64,
+    dy: f64,
+    cx: f64,
+    cy: f64,
+    maxIterations: i32,
+    // also return fractional iteration counts
+    #[serde(default)]
+    smooth: bool,
+    // square of the bailout radius
+    #[serde(default = "default_bailout")]
+    bailout: f64,
+}
+
+async fn compute_julia(julia_coords: web::Json<JuliaCoords>) -> HttpResponse {
+    let bailout = julia_coords.bailout;
+    if !(bailout >= MIN_BAILOUT && bailout.is_finite()) {
+        return HttpResponse::BadRequest().body(format!("bailout must be at least {MIN_BAILOUT}"));
+    }
+
+    let periodicity = unsafe { PERIODICITY };
+    let mut pixels = vec![vec![Pixel::default(); julia_coords.columns]; julia_coords.rows];
+    for (i, row) in pixels.iter_mut().enumerate() {
+        let y = julia_coords.ymax - (julia_coords.firstRow + i) as f64*julia_coords.dy;
+        for (j, pixel) in row.iter_mut().enumerate() {
+            *pixel = count_iterations_julia_smooth(julia_coords.xmin + j as f64*julia_coords.dx, y, julia_coords.cx, julia_coords.cy, julia_coords.maxIterations, bailout, periodicity).into();
+        }
+    }
+
+    mandelbrot_response(pixels, julia_coords.smooth, false, false, false, bailout, 2)
+}
+
+// same as MandelbrotCoordsHP, plus the fixed c = cx + cy*i in u32 chunks
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct JuliaCoordsHP {
+    columns: usize,
+    rows: usize,
+    xmin: Vec<u32>,
+    dx: Vec<u32>,
+    ymax: Vec<u32>,
+    dy: Vec<u32>,
+    cx: Vec<u32>,
+    cy: Vec<u32>,
+    maxIterations: i32,
+    // also return fractional iteration counts
+    #[serde(default)]
+    smooth: bool,
+    // square of the bailout radius
+    #[serde(default = "default_bailout")]
+    bailout: f64,
+}
+
+async fn compute_julia_hp(julia_coords_hp: web::Json<JuliaCoordsHP>) -> HttpResponse {
+    if let Err(message) = check_hp_view(&julia_coords_hp.xmin, &julia_coords_hp.dx, &julia_coords_hp.ymax, &julia_coords_hp.dy) {
+        return HttpResponse::BadRequest().body(message);
+    }
+    if julia_coords_hp.cx.is_empty() || julia_coords_hp.cy.is_empty() {
+        return HttpResponse::BadRequest().body("cx and cy must have at least 1 chunk");
+    }
+    let bailout = julia_coords_hp.bailout;
+    if !(MIN_BAILOUT..=max_bailout_hp::<u128>(2)).contains(&bailout) {
+        return HttpResponse::BadRequest().body(format!("bailout must be between {MIN_BAILOUT} and {}", max_bailout_hp::<u128>(2)));
+    }
+
+    let precision = match choose_hp_precision(&julia_coords_hp.xmin, &julia_coords_hp.dx, &julia_coords_hp.ymax, &julia_coords_hp.dy,
+        julia_coords_hp.columns, julia_coords_hp.rows) {
+        Precision::HP { u32_chunks } => Precision::HP { u32_chunks: hp_u32_chunks(u32_chunks, julia_coords_hp.xmin.len()) },
+        precision => precision,
+    };
+    let pixels = match precision {
+        Precision::HP { u32_chunks } => match u_type_for_bailout(bailout, 2) {
+            32 => compute_julia_hp_u::<u32>(&julia_coords_hp, u32_chunks),
+            64 => compute_julia_hp_u::<u64>(&julia_coords_hp, u32_chunks),
+            128 => compute_julia_hp_u::<u128>(&julia_coords_hp, u32_chunks),
+            _ => panic!("illegal size!")
+        },
+        _ => compute_julia_low_precision(&julia_coords_hp, precision),
+    };
+    with_precision(mandelbrot_response(pixels, julia_coords_hp.smooth, false, false, false, bailout, 2), precision)
+}
+
+// compute the request in f64 or double-double
+fn compute_julia_low_precision(julia_coords_hp: &JuliaCoordsHP, precision: Precision) -> Vec<Vec<Pixel>> {
+    let to_dd = | a: &[u32] | HpFixed::<u64>::from_u32_chunks(a).to_double_double();
+    let (xmin, dx) = (to_dd(&julia_coords_hp.xmin), to_dd(&julia_coords_hp.dx));
+    let (ymax, dy) = (to_dd(&julia_coords_hp.ymax), to_dd(&julia_coords_hp.dy));
+    let (cx, cy) = (to_dd(&julia_coords_hp.cx), to_dd(&julia_coords_hp.cy));
+    let max_iterations = julia_coords_hp.maxIterations;
+    let bailout = julia_coords_hp.bailout;
+    let periodicity = unsafe { PERIODICITY };
+
+    let mut pixels = vec![vec![Pixel::default(); julia_coords_hp.columns]; julia_coords_hp.rows];
+    for (i, row) in pixels.iter_mut().enumerate() {
+        let y = ymax - DoubleDouble::from(i as f64)*dy;
+        for (j, pixel) in row.iter_mut().enumerate() {
+            let x = xmin + DoubleDouble::from(j as f64)*dx;
+            *pixel = if precision == Precision::DoubleDouble {
+                count_iterations_dd_julia_smooth(x, y, cx, cy, max_iterations, bailout, periodicity).into()
+            } else {
+                count_iterations_julia_smooth(x.hi(), y.hi(), cx.hi(), cy.hi(), max_iterations, bailout, periodicity).into()
+            };
+        }
+    }
+    pixels
+}
+
+fn compute_julia_hp_u<T>(julia_coords_hp: &JuliaCoordsHP, u32_chunks: usize) -> Vec<Vec<Pixel>>
+where T: Limb,
+{
+    let xmin = u32_to_t::<T>(&julia_coords_hp.xmin);
+    let dx = u32_to_t::<T>(&julia_coords_hp.dx);
+    let yval = u32_to_t::<T>(&julia_coords_hp.ymax);
+    let dy = u32_to_t::<T>(&julia_coords_hp.dy);
+    let cx = u32_to_t::<T>(&julia_coords_hp.cx);
+    let cy = u32_to_t::<T>(&julia_coords_hp.cy);
+    compute_julia_hp_t(&xmin, &dx, &yval, &dy, &cx, &cy, julia_coords_hp.rows, julia_coords_hp.columns, julia_coords_hp.maxIterations, julia_coords_hp.bailout, u32_chunks, unsafe { NUM_THREADS })
+}
+
+// c can have a different number of chunks than the pixels; it is truncated or zero extended to match
+#[allow(clippy::too_many_arguments)]
+pub fn compute_julia_hp_t<T>(xmin: &[T], dx: &[T], yval: &[T], dy: &[T], cx: &[T], cy: &[T], rows: usize, columns: usize, max_iter: i32, bailout: f64, u32_chunks: usize, num_threads: usize) -> Vec<Vec<Pixel>>
+where T: Limb,
+{
+    let chunks = t_chunks::<T>(u32_chunks);
+    let mut cx = cx.to_vec();
+    cx.resize(chunks, T::zero());
+    let mut cy = cy.to_vec();
+    cy.resize(chunks, T::zero());
+
+    compute_hp_rows(xmin, dx, yval, dy, rows, columns, chunks, num_threads, | hp_data, x, y | {
+        count_iterations_hp_julia_smooth(hp_data, x, y, &cx, &cy, max_iter, bailout).into()
+    })
+}
+
+
+// *** finding minibrots *** //
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct NucleusCoords {
+    // the point to search around and the search radius, as decimal strings
+    x: String,
+    y: String,
+    radius: String,
+    #[serde(default = "default_max_period")]
+    maxPeriod: i32,
+    // image size for the suggested view
+    #[serde(default = "default_width")]
+    width: usize,
+    #[serde(default = "default_height")]
+    height: usize,
+}
+
+fn default_max_period() -> i32 {
+    100_000
+}
+
+// the reference orbit for the period search holds this many points at most
+const MAX_NUCLEUS_PERIOD: i32 = 1_000_000;
+
+// a sign, a decimal point and an exponent on top of the most digits a decimal string can have
+const MAX_DECIMAL_LENGTH: usize = MAX_DECIMAL_DIGITS + 16;
+
+fn default_width() -> usize {
+    800
+}
+
+fn default_height() -> usize {
+    600
+}
+
+#[derive(Serialize)]
+#[allow(non_snake_case)]
+struct NucleusResponse {
+    // the nucleus, to the precision it was found to
+    x: String,
+    y: String,
+    period: usize,
+    // approximate size of the minibrot relative to the whole set
+    size: String,
+    // a view of the minibrot, in the format of the client's saved examples
+    xml: String,
+}
+
+async fn find_nucleus_xml(nucleus_coords: web::Json<NucleusCoords>) -> HttpResponse {
+    let check_length = | name: &str, s: &str | if s.len() > MAX_DECIMAL_LENGTH { Err(format!("{name}: too long")) } else { Ok(()) };
+    let parse = | name: &str, s: &str | check_length(name, s).and_then(|_| s.parse::<HpFixed<u64>>().map_err(| e | format!("{name}: {e}")));
+    // the radius only needs the precision of a FloatExp
+    let parse_radius = | s: &str | check_length("radius", s).and_then(|_| s.parse::<FloatExp>().map_err(| e | format!("radius: {e}")));
+    let (x, y, radius) = match (parse("x", &nucleus_coords.x), parse("y", &nucleus_coords.y), parse_radius(&nucleus_coords.radius)) {
+        (Ok(x), Ok(y), Ok(radius)) => (x, y, radius),
+        (Err(message), _, _) | (_, Err(message), _) | (_, _, Err(message)) => return HttpResponse::BadRequest().body(message),
+    };
+    if radius <= FloatExp::zero() || nucleus_coords.width == 0 || nucleus_coords.height == 0 {
+        return HttpResponse::BadRequest().body("radius, width and height must be positive");
+    }
+    if chunks_for_size::<u64>(radius) > max_nucleus_chunks::<u64>() {
+        return HttpResponse::BadRequest().body(format!("radius needs more than {MAX_DECIMAL_DIGITS} digits"));
+    }
+    if !(1..=MAX_NUCLEUS_PERIOD).contains(&nucleus_coords.maxPeriod) {
+        return HttpResponse::BadRequest().body(format!("maxPeriod must be between 1 and {MAX_NUCLEUS_PERIOD}"));
+    }
+
+    let Some(nucleus) = find_nucleus(&x, &y, radius, nucleus_coords.maxPeriod) else {
+        return HttpResponse::NotFound().body(format!("no nucleus with a period up to {} and a minibrot within {MAX_DECIMAL_DIGITS} digits found", nucleus_coords.maxPeriod));
+    };
+
+    // the minibrot spans about 2.25 times its size, so show 5 times its size across the shorter side
+    let (width, height) = (nucleus_coords.width as f64, nucleus_coords.height as f64);
+    let span = FloatExp::from(5.0)*nucleus.size;
+    let (half_width, half_height) = (FloatExp::from(0.5*width/width.min(height))*span, FloatExp::from(0.5*height/width.min(height))*span);
+    let to_hp = | d: FloatExp | HpFixed::from_chunks(floatexp_to_t::<u64>(d, nucleus.x.len()));
+    let (xmin, xmax) = (&nucleus.x - &to_hp(half_width), &nucleus.x + &to_hp(half_width));
+    let (ymin, ymax) = (&nucleus.y - &to_hp(half_height), &nucleus.y + &to_hp(half_height));
+
+    // enough decimal places for a thousandth of a pixel
+    let places = (3.0 - log10(span*FloatExp::from(1.0/width.min(height)))).ceil().max(1.0) as usize;
+    // about 100 iterations of the minibrot's own
+    let max_iterations = (100*nucleus.period).max(1000);
+    let xml = format!(
+        "<?xml version='1.0'?>\n<mandelbrot_settings_2>\n\
+        <image_size width='{}' height='{}'/>\n\
+        <limits>\n   <xmin>{xmin:.places$}</xmin>\n   <xmax>{xmax:.places$}</xmax>\n   <ymin>{ymin:.places$}</ymin>\n   <ymax>{ymax:.places$}</ymax>\n</limits>\n\
+        <palette colorType='HSB'>\n   <divisionPoint position='0' color='0;1;1'/>\n   <divisionPoint position='1' color='1;1;1'/>\n</palette>\n\
+        <palette_mapping length='0' offset='0'/>\n\
+        <max_iterations value='{max_iterations}'/>\n\
+        </mandelbrot_settings_2>\n",
+        nucleus_coords.width, nucleus_coords.height);
+
+    HttpResponse::Ok().json(NucleusResponse {
+        x: nucleus.x.to_string(),
+        y: nucleus.y.to_string(),
+        period: nucleus.period,
+        size: to_scientific(nucleus.size),
+        xml,
+    })
+}
+
+// log10 of a positive FloatExp, which can be far outside the f64 range
+fn log10(x: FloatExp) -> f64 {
+    (x.exponent() as f64 + x.mantissa().log2())*std::f64::consts::LOG10_2
+}
+
+// a positive FloatExp as eg 3.43e-145
+fn to_scientific(x: FloatExp) -> String {
+    let log = log10(x);
+    let (mut mantissa, mut exponent) = (10f64.powf(log - log.floor()), log.floor());
+    // so it doesn't round up to 10.00
+    if mantissa >= 9.995 {
+        mantissa /= 10.0;
+        exponent += 1.0;
+    }
+    format!("{mantissa:.2}e{exponent}")
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{ test, web, App };
+    use actix_web::http::{ StatusCode, header::ContentType };
+    use super::*;
+
+    async fn post(uri: &str, body: String) -> StatusCode {
+        let app = test::init_service(App::new()
+            .route("/mb-compute", web::post().to(compute_mandelbrot))
+            .route("/mb-computeHP", web::post().to(compute_mandelbrot_hp))
+            .route("/mb-computeJuliaHP", web::post().to(compute_julia_hp))).await;
+        let request = test::TestRequest::post().uri(uri).insert_header(ContentType::json()).set_payload(body).to_request();
+        test::call_service(&app, request).await.status()
+    }
+
+    // -2 to -0.5 at y = 0.5
+    fn mandelbrot_request(power: u32) -> String {
+        format!(r#"{{ "columns": 4, "firstRow": 0, "rows": 1, "xmin": -2.0, "dx": 0.5, "ymax": 0.5, "dy": 0.5, "maxIterations": 100, "smooth": true, "power": {power} }}"#)
+    }
+
+    // -2 to -0.5 in 16 bit chunks; at MAX_POWER only MIN_BAILOUT fits in the chunks
+    fn mandelbrot_hp_request(power: u32) -> String {
+        format!(r#"{{ "columns": 4, "rows": 1, "xmin": [65534, 0, 0], "dx": [0, 32768, 0], "ymax": [0, 32768, 0], "dy": [0, 32768, 0],
+            "maxIterations": 100, "smooth": true, "bailout": {MIN_BAILOUT}, "power": {power} }}"#)
+    }
+
+    #[actix_web::test]
+    async fn power_above_max_rejected() {
+        for power in [2, MAX_POWER] {
+            assert_eq!(post("/mb-compute", mandelbrot_request(power)).await, StatusCode::OK, "power {power}");
+            assert_eq!(post("/mb-computeHP", mandelbrot_hp_request(power)).await, StatusCode::OK, "power {power}");
+        }
+        for power in [1, MAX_POWER + 1, u32::MAX] {
+            assert_eq!(post("/mb-compute", mandelbrot_request(power)).await, StatusCode::BAD_REQUEST, "power {power}");
+            assert_eq!(post("/mb-computeHP", mandelbrot_hp_request(power)).await, StatusCode::BAD_REQUEST, "power {power}");
+        }
+    }
+
+    fn hp_view_request(xmin: &str, dx: &str, cx: &str) -> String {
+        format!(r#"{{ "columns": 4, "rows": 1, "xmin": {xmin}, "dx": {dx}, "ymax": {dx}, "dy": {dx}, "cx": {cx}, "cy": {cx}, "maxIterations": 100 }}"#)
+    }
+
+    #[actix_web::test]
+    async fn short_or_mismatched_hp_view_rejected() {
+        for uri in ["/mb-computeHP", "/mb-computeJuliaHP"] {
+            // a fraction chunk is all that's needed
+            assert_eq!(post(uri, hp_view_request("[65534, 0]", "[0, 32768]", "[0]")).await, StatusCode::OK, "{uri}");
+            // no chunks, no fraction chunk, a deep dx with a short xmin, and a short dx
+            for (xmin, dx) in [("[]", "[]"), ("[65534]", "[0]"), ("[65534, 0]", "[0, 0, 0, 0, 0, 1]"), ("[65534, 0, 0]", "[0, 32768]")] {
+                assert_eq!(post(uri, hp_view_request(xmin, dx, "[0]")).await, StatusCode::BAD_REQUEST, "{uri} {xmin} {dx}");
+            }
+        }
+        assert_eq!(post("/mb-computeJuliaHP", hp_view_request("[65534, 0]", "[0, 32768]", "[]")).await, StatusCode::BAD_REQUEST);
+    }
+}
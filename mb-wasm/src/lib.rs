@@ -3,6 +3,9 @@
     By Bill Wood, Jan/Feb 2023
 */
 
+// the exported functions are called from JavaScript with pointers into wasm memory
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
     // `set_panic_hook` function at least once during initialization, and then
@@ -17,9 +20,10 @@ pub fn set_panic_hook() {
 
 // *** alloc/dealloc for JavaScript *** //
 // https://radu-matei.com/blog/practical-guide-to-wasm-memory/
+// (only exported on wasm, where there is no libc malloc for it to replace)
 use std::alloc::{alloc, dealloc, Layout};
 
-#[no_mangle]
+#[cfg_attr(target_arch = "wasm32", no_mangle)]
 pub extern "C" fn malloc(size: u32) -> *mut u8 {
     let align = std::mem::align_of::<usize>();
     unsafe {
@@ -28,7 +32,7 @@ pub extern "C" fn malloc(size: u32) -> *mut u8 {
     }
 }
 
-#[no_mangle]
+#[cfg_attr(target_arch = "wasm32", no_mangle)]
 pub extern "C"  fn dalloc(ptr: *mut u8, size: u32) {
     let align = std::mem::align_of::<usize>();
     unsafe {
@@ -40,14 +44,210 @@ pub extern "C"  fn dalloc(ptr: *mut u8, size: u32) {
 
 use mb_arith::*;
 
+// *** options *** //
+// periodicity checking is on by default; it doesn't change the iteration counts.  It's an option of this
+// wasm instance, passed to the kernels by each export
+use std::sync::atomic::{ AtomicBool, Ordering };
+
+static PERIODICITY: AtomicBool = AtomicBool::new(true);
+
+#[no_mangle]
+pub extern "C" fn set_periodicity(on: u32) {
+    PERIODICITY.store(on != 0, Ordering::Relaxed);
+}
+
+fn periodicity() -> bool {
+    PERIODICITY.load(Ordering::Relaxed)
+}
+
+// *** Multibrot powers *** //
+// z^0 and z^1 aren't Multibrots, and would give infinite or NaN smooth counts, and powers above MAX_POWER
+// take too long and overflow; the server rejects them, and here they're taken as 2 or MAX_POWER
+fn multibrot_power(power: u32) -> u32 {
+    power.clamp(2, MAX_POWER)
+}
+
+// *** bailouts *** //
+// bailouts below MIN_BAILOUT would let points in the set escape, and 0 or less would give NaN smooth counts;
+// the server rejects them, and here they're taken as MIN_BAILOUT.  NaN or infinite bailouts are taken as
+// DEFAULT_BAILOUT
+fn limit_bailout(bailout: f64) -> f64 {
+    if bailout.is_finite() {
+        bailout.max(MIN_BAILOUT)
+    } else {
+        DEFAULT_BAILOUT
+    }
+}
+
 // *** low precision *** //
 #[no_mangle]
 pub extern "C" fn compute_mandelbrot(xmin: f64, dx: f64, columns: u32, y: f64, max_iterations: i32, iteration_counts: *mut i32) {
 
     let columns = columns as usize;
     let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
-    for i in 0..columns {
-        iteration_counts[i] = count_iterations(xmin + (i as f64*dx), y, max_iterations);
+    for (count, (pixel_count, _)) in iteration_counts.iter_mut().zip(count_iterations_smooth_row(xmin, dx, columns, y, max_iterations, DEFAULT_BAILOUT, periodicity())) {
+        *count = pixel_count;
+    }
+}
+
+// same as compute_mandelbrot, with a bailout and a Multibrot power, also filling in the fractional iteration counts
+#[no_mangle]
+pub extern "C" fn compute_mandelbrot_smooth(xmin: f64, dx: f64, columns: u32, y: f64, max_iterations: i32, bailout: f64, power: u32, iteration_counts: *mut i32, smooth_counts: *mut f32) {
+
+    let bailout = limit_bailout(bailout);
+    let power = multibrot_power(power);
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+    let smooth_counts = unsafe { std::slice::from_raw_parts_mut(smooth_counts, columns) };
+    if power == 2 {
+        for ((count, smooth_count), (pixel_count, norm)) in iteration_counts.iter_mut().zip(smooth_counts.iter_mut())
+            .zip(count_iterations_smooth_row(xmin, dx, columns, y, max_iterations, bailout, periodicity())) {
+            *count = pixel_count;
+            *smooth_count = smooth_iteration_count(*count, norm, bailout, power) as f32;
+        }
+        return;
+    }
+    for (i, (count, smooth_count)) in iteration_counts.iter_mut().zip(smooth_counts.iter_mut()).enumerate() {
+        let norm;
+        (*count, norm) = count_iterations_multibrot_smooth(xmin + (i as f64*dx), y, max_iterations, bailout, power, periodicity());
+        *smooth_count = smooth_iteration_count(*count, norm, bailout, power) as f32;
+    }
+}
+
+// same as compute_mandelbrot, with a bailout, also filling in the distances to the set in pixels
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_mandelbrot_distance(xmin: f64, dx: f64, columns: u32, y: f64, max_iterations: i32, bailout: f64, iteration_counts: *mut i32, distances: *mut f32) {
+
+    let bailout = limit_bailout(bailout);
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+    let distances = unsafe { std::slice::from_raw_parts_mut(distances, columns) };
+    for (i, (count, distance)) in iteration_counts.iter_mut().zip(distances.iter_mut()).enumerate() {
+        let pixel_distance;
+        (*count, _, pixel_distance) = count_iterations_distance(xmin + (i as f64*dx), y, dx, max_iterations, bailout, periodicity());
+        *distance = pixel_distance as f32;
+    }
+}
+
+// the interior data outputs of the compute_mandelbrot_*_interior functions, one element per column
+struct InteriorOutputs<'a> {
+    final_zx: &'a mut [f32],
+    final_zy: &'a mut [f32],
+    periods: &'a mut [i32],
+    distances: &'a mut [f32],
+}
+
+impl InteriorOutputs<'_> {
+    fn new(columns: usize, final_zx: *mut f32, final_zy: *mut f32, periods: *mut i32, distances: *mut f32) -> Self {
+        unsafe {
+            InteriorOutputs {
+                final_zx: std::slice::from_raw_parts_mut(final_zx, columns),
+                final_zy: std::slice::from_raw_parts_mut(final_zy, columns),
+                periods: std::slice::from_raw_parts_mut(periods, columns),
+                distances: std::slice::from_raw_parts_mut(distances, columns),
+            }
+        }
+    }
+
+    fn set(&mut self, i: usize, interior: Interior) {
+        self.final_zx[i] = interior.zx as f32;
+        self.final_zy[i] = interior.zy as f32;
+        self.periods[i] = interior.period;
+        self.distances[i] = interior.distance as f32;
+    }
+}
+
+// same as compute_mandelbrot, with a bailout, also filling in the final z, the period of the attracting
+// cycle and the interior distance in pixels; the period is 0 for points that escaped
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_mandelbrot_interior(xmin: f64, dx: f64, columns: u32, y: f64, max_iterations: i32, bailout: f64, iteration_counts: *mut i32,
+    final_zx: *mut f32, final_zy: *mut f32, periods: *mut i32, interior_distances: *mut f32) {
+
+    let bailout = limit_bailout(bailout);
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+    let mut outputs = InteriorOutputs::new(columns, final_zx, final_zy, periods, interior_distances);
+    for (i, count) in iteration_counts.iter_mut().enumerate() {
+        let interior;
+        (*count, _, _, interior) = count_iterations_interior(xmin + (i as f64*dx), y, dx, max_iterations, bailout);
+        outputs.set(i, interior);
+    }
+}
+
+
+// *** double-double precision *** //
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_mandelbrot_dd(xmin: f64, xmin_lo: f64, dx: f64, dx_lo: f64, columns: u32, y: f64, y_lo: f64, max_iterations: i32, iteration_counts: *mut i32) {
+
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+    let xmin = DoubleDouble::new(xmin, xmin_lo);
+    let dx = DoubleDouble::new(dx, dx_lo);
+    let y = DoubleDouble::new(y, y_lo);
+    for (i, count) in iteration_counts.iter_mut().enumerate() {
+        *count = count_iterations_dd(xmin + DoubleDouble::from(i as f64)*dx, y, max_iterations, DEFAULT_BAILOUT, periodicity());
+    }
+}
+
+// same as compute_mandelbrot_dd, with a bailout and a Multibrot power, also filling in the fractional iteration counts
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_mandelbrot_dd_smooth(xmin: f64, xmin_lo: f64, dx: f64, dx_lo: f64, columns: u32, y: f64, y_lo: f64, max_iterations: i32, bailout: f64, power: u32, iteration_counts: *mut i32, smooth_counts: *mut f32) {
+
+    let bailout = limit_bailout(bailout);
+    let power = multibrot_power(power);
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+    let smooth_counts = unsafe { std::slice::from_raw_parts_mut(smooth_counts, columns) };
+    let xmin = DoubleDouble::new(xmin, xmin_lo);
+    let dx = DoubleDouble::new(dx, dx_lo);
+    let y = DoubleDouble::new(y, y_lo);
+    for (i, (count, smooth_count)) in iteration_counts.iter_mut().zip(smooth_counts.iter_mut()).enumerate() {
+        let norm;
+        (*count, norm) = count_iterations_dd_multibrot_smooth(xmin + DoubleDouble::from(i as f64)*dx, y, max_iterations, bailout, power, periodicity());
+        *smooth_count = smooth_iteration_count(*count, norm, bailout, power) as f32;
+    }
+}
+
+// same as compute_mandelbrot_dd, with a bailout, also filling in the distances to the set in pixels
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_mandelbrot_dd_distance(xmin: f64, xmin_lo: f64, dx: f64, dx_lo: f64, columns: u32, y: f64, y_lo: f64, max_iterations: i32, bailout: f64, iteration_counts: *mut i32, distances: *mut f32) {
+
+    let bailout = limit_bailout(bailout);
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+    let distances = unsafe { std::slice::from_raw_parts_mut(distances, columns) };
+    let xmin = DoubleDouble::new(xmin, xmin_lo);
+    let dx = DoubleDouble::new(dx, dx_lo);
+    let y = DoubleDouble::new(y, y_lo);
+    for (i, (count, distance)) in iteration_counts.iter_mut().zip(distances.iter_mut()).enumerate() {
+        let pixel_distance;
+        (*count, _, pixel_distance) = count_iterations_dd_distance(xmin + DoubleDouble::from(i as f64)*dx, y, dx.hi(), max_iterations, bailout, periodicity());
+        *distance = pixel_distance as f32;
+    }
+}
+
+// same as compute_mandelbrot_interior, in double-double precision
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_mandelbrot_dd_interior(xmin: f64, xmin_lo: f64, dx: f64, dx_lo: f64, columns: u32, y: f64, y_lo: f64, max_iterations: i32, bailout: f64,
+    iteration_counts: *mut i32, final_zx: *mut f32, final_zy: *mut f32, periods: *mut i32, interior_distances: *mut f32) {
+
+    let bailout = limit_bailout(bailout);
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+    let mut outputs = InteriorOutputs::new(columns, final_zx, final_zy, periods, interior_distances);
+    let xmin = DoubleDouble::new(xmin, xmin_lo);
+    let dx = DoubleDouble::new(dx, dx_lo);
+    let y = DoubleDouble::new(y, y_lo);
+    for (i, count) in iteration_counts.iter_mut().enumerate() {
+        let interior;
+        (*count, _, _, interior) = count_iterations_dd_interior(xmin + DoubleDouble::from(i as f64)*dx, y, dx.hi(), max_iterations, bailout);
+        outputs.set(i, interior);
     }
 }
 
@@ -56,15 +256,12 @@ pub extern "C" fn compute_mandelbrot(xmin: f64, dx: f64, columns: u32, y: f64, m
 use core::mem::size_of;
 type UInt = u64;
 
-#[no_mangle]
-pub extern "C" fn compute_mandelbrot_hp(xmin: *const u32, len: u32, dx: *const u32, columns: u32, y: *const u32, max_iterations: i32, iteration_counts: *mut i32) {
-
+// the chunk count to iterate with, the first x, dx and y of a row in UInt chunks, and the HPData for them
+fn hp_args(xmin: *const u32, len: u32, dx: *const u32, y: *const u32) -> (usize, Vec<UInt>, Vec<UInt>, Vec<UInt>, HPData<UInt>) {
     let len = len as usize;
     let xmin = unsafe { std::slice::from_raw_parts(xmin, len) };
     let dx = unsafe { std::slice::from_raw_parts(dx, len) };
     let y = unsafe { std::slice::from_raw_parts(y, len) };
-    let columns = columns as usize;
-    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
 
     // ignore lowest 16 bits for efficiency during Mandelbrot calculation, has no impact on image quality
     // use all bits for incrementing x_val though
@@ -72,16 +269,174 @@ pub extern "C" fn compute_mandelbrot_hp(xmin: *const u32, len: u32, dx: *const u
     // chunks: 1 for the integral part, plus however many T elements are needed for the fractional part
     let chunks = 1 + {
         let t_to_u32_size_ratio = size_of::<UInt>()/size_of::<u32>();
-        (u32_chunks - 1 + t_to_u32_size_ratio - 1)/t_to_u32_size_ratio
+        (u32_chunks - 1).div_ceil(t_to_u32_size_ratio)
     };
 
-    let mut x_val = u32_to_t::<UInt>(xmin);
-    let dx = u32_to_t::<UInt>(dx);
-    let y = u32_to_t::<UInt>(y);
-    let mut hp_data = HPData::new(chunks);
+    (chunks, u32_to_t::<UInt>(xmin), u32_to_t::<UInt>(dx), u32_to_t::<UInt>(y), HPData::with_periodicity(chunks, periodicity()))
+}
+
+// limit_bailout, also limited to what fits in the integer part of a UInt chunk
+fn hp_bailout(bailout: f64, power: u32) -> f64 {
+    limit_bailout(bailout).min(max_bailout_hp::<UInt>(power))
+}
+
+// a Julia seed of c_len u32 chunks, truncated or zero extended to chunks UInt chunks
+fn hp_seed(cx: *const u32, cy: *const u32, c_len: u32, chunks: usize) -> (Vec<UInt>, Vec<UInt>) {
+    let c_len = c_len as usize;
+    let mut cx = u32_to_t::<UInt>(unsafe { std::slice::from_raw_parts(cx, c_len) });
+    cx.resize(chunks, 0);
+    let mut cy = u32_to_t::<UInt>(unsafe { std::slice::from_raw_parts(cy, c_len) });
+    cy.resize(chunks, 0);
+    (cx, cy)
+}
+
+#[no_mangle]
+pub extern "C" fn compute_mandelbrot_hp(xmin: *const u32, len: u32, dx: *const u32, columns: u32, y: *const u32, max_iterations: i32, iteration_counts: *mut i32) {
+
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+
+    let (chunks, mut x_val, dx, y, mut hp_data) = hp_args(xmin, len, dx, y);
+
+    for count in iteration_counts.iter_mut() {
+        *count = count_iterations_hp(&mut hp_data, &x_val[0..chunks], &y[0..chunks], max_iterations, DEFAULT_BAILOUT);
+        incr(&mut x_val, &dx);
+    }
+}
+
+// same as compute_mandelbrot_hp, with a bailout and a Multibrot power, also filling in the fractional
+// iteration counts; the bailout is limited to what fits in the integer part of a UInt chunk
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_mandelbrot_hp_smooth(xmin: *const u32, len: u32, dx: *const u32, columns: u32, y: *const u32, max_iterations: i32, bailout: f64, power: u32, iteration_counts: *mut i32, smooth_counts: *mut f32) {
+
+    let power = multibrot_power(power);
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+    let smooth_counts = unsafe { std::slice::from_raw_parts_mut(smooth_counts, columns) };
+
+    let (chunks, mut x_val, dx, y, mut hp_data) = hp_args(xmin, len, dx, y);
+    let bailout = hp_bailout(bailout, power);
+
+    for (count, smooth_count) in iteration_counts.iter_mut().zip(smooth_counts.iter_mut()) {
+        let norm;
+        (*count, norm) = count_iterations_hp_multibrot_smooth(&mut hp_data, &x_val[0..chunks], &y[0..chunks], max_iterations, bailout, power);
+        *smooth_count = smooth_iteration_count(*count, norm, bailout, power) as f32;
+        incr(&mut x_val, &dx);
+    }
+}
+
+// same as compute_mandelbrot_hp, with a bailout, also filling in the distances to the set in pixels; the
+// bailout is limited to what fits in the integer part of a UInt chunk
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_mandelbrot_hp_distance(xmin: *const u32, len: u32, dx: *const u32, columns: u32, y: *const u32, max_iterations: i32, bailout: f64, iteration_counts: *mut i32, distances: *mut f32) {
+
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+    let distances = unsafe { std::slice::from_raw_parts_mut(distances, columns) };
+
+    let (chunks, mut x_val, dx, y, mut hp_data) = hp_args(xmin, len, dx, y);
+    let bailout = hp_bailout(bailout, 2);
+    let pixel_size = t_to_floatexp(&dx);
+
+    for (count, distance) in iteration_counts.iter_mut().zip(distances.iter_mut()) {
+        let pixel_distance;
+        (*count, _, pixel_distance) = count_iterations_hp_distance(&mut hp_data, &x_val[0..chunks], &y[0..chunks], pixel_size, max_iterations, bailout);
+        *distance = pixel_distance as f32;
+        incr(&mut x_val, &dx);
+    }
+}
+
+// same as compute_mandelbrot_interior, in high precision; the bailout is limited to what fits in the
+// integer part of a UInt chunk
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_mandelbrot_hp_interior(xmin: *const u32, len: u32, dx: *const u32, columns: u32, y: *const u32, max_iterations: i32, bailout: f64,
+    iteration_counts: *mut i32, final_zx: *mut f32, final_zy: *mut f32, periods: *mut i32, interior_distances: *mut f32) {
+
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+    let mut outputs = InteriorOutputs::new(columns, final_zx, final_zy, periods, interior_distances);
+
+    let (chunks, mut x_val, dx, y, mut hp_data) = hp_args(xmin, len, dx, y);
+    let bailout = hp_bailout(bailout, 2);
+    let pixel_size = t_to_floatexp(&dx);
+
+    for (i, count) in iteration_counts.iter_mut().enumerate() {
+        let interior;
+        (*count, _, _, interior) = count_iterations_hp_interior(&mut hp_data, &x_val[0..chunks], &y[0..chunks], pixel_size, max_iterations, bailout);
+        outputs.set(i, interior);
+        incr(&mut x_val, &dx);
+    }
+}
+
+
+// *** Julia sets *** //
+// same as compute_mandelbrot, iterating z = z*z + c from z = the pixel, with c = cx + cy*i
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_julia(xmin: f64, dx: f64, columns: u32, y: f64, cx: f64, cy: f64, max_iterations: i32, iteration_counts: *mut i32) {
+
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+    for (i, count) in iteration_counts.iter_mut().enumerate() {
+        *count = count_iterations_julia(xmin + (i as f64*dx), y, cx, cy, max_iterations, DEFAULT_BAILOUT, periodicity());
+    }
+}
+
+// same as compute_julia, with a bailout, also filling in the fractional iteration counts
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_julia_smooth(xmin: f64, dx: f64, columns: u32, y: f64, cx: f64, cy: f64, max_iterations: i32, bailout: f64, iteration_counts: *mut i32, smooth_counts: *mut f32) {
+
+    let bailout = limit_bailout(bailout);
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+    let smooth_counts = unsafe { std::slice::from_raw_parts_mut(smooth_counts, columns) };
+    for (i, (count, smooth_count)) in iteration_counts.iter_mut().zip(smooth_counts.iter_mut()).enumerate() {
+        let norm;
+        (*count, norm) = count_iterations_julia_smooth(xmin + (i as f64*dx), y, cx, cy, max_iterations, bailout, periodicity());
+        *smooth_count = smooth_iteration_count(*count, norm, bailout, 2) as f32;
+    }
+}
+
+// same as compute_mandelbrot_hp, with c = cx + cy*i in c_len u32 chunks; c is truncated or zero extended
+// to the precision of the pixels
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_julia_hp(xmin: *const u32, len: u32, dx: *const u32, columns: u32, y: *const u32, cx: *const u32, cy: *const u32, c_len: u32, max_iterations: i32, iteration_counts: *mut i32) {
+
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+
+    let (chunks, mut x_val, dx, y, mut hp_data) = hp_args(xmin, len, dx, y);
+    let (cx, cy) = hp_seed(cx, cy, c_len, chunks);
+
+    for count in iteration_counts.iter_mut() {
+        *count = count_iterations_hp_julia(&mut hp_data, &x_val[0..chunks], &y[0..chunks], &cx, &cy, max_iterations, DEFAULT_BAILOUT);
+        incr(&mut x_val, &dx);
+    }
+}
+
+// same as compute_julia_hp, with a bailout, also filling in the fractional iteration counts; the bailout
+// is limited to what fits in the integer part of a UInt chunk
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn compute_julia_hp_smooth(xmin: *const u32, len: u32, dx: *const u32, columns: u32, y: *const u32, cx: *const u32, cy: *const u32, c_len: u32, max_iterations: i32, bailout: f64, iteration_counts: *mut i32, smooth_counts: *mut f32) {
+
+    let columns = columns as usize;
+    let iteration_counts = unsafe { std::slice::from_raw_parts_mut(iteration_counts, columns) };
+    let smooth_counts = unsafe { std::slice::from_raw_parts_mut(smooth_counts, columns) };
+
+    let (chunks, mut x_val, dx, y, mut hp_data) = hp_args(xmin, len, dx, y);
+    let (cx, cy) = hp_seed(cx, cy, c_len, chunks);
+    let bailout = hp_bailout(bailout, 2);
 
-    for i in 0..columns {
-        iteration_counts[i] = count_iterations_hp(&mut hp_data, &x_val[0..chunks], &y[0..chunks], max_iterations);
+    for (count, smooth_count) in iteration_counts.iter_mut().zip(smooth_counts.iter_mut()) {
+        let norm;
+        (*count, norm) = count_iterations_hp_julia_smooth(&mut hp_data, &x_val[0..chunks], &y[0..chunks], &cx, &cy, max_iterations, bailout);
+        *smooth_count = smooth_iteration_count(*count, norm, bailout, 2) as f32;
         incr(&mut x_val, &dx);
     }
 }